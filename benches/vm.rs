@@ -0,0 +1,93 @@
+//! Compares the tree-walking interpreter with the bytecode VM on the recipes in `programs/`.
+//!
+//! Run with `cargo bench --bench vm`.
+
+use std::time::{Duration, Instant};
+
+use spatula::{
+    interpreter::{compile, Interpreter, Limits, MemoryIo, NoShuffle},
+    parser::parse,
+};
+
+/// Input for recipes that take ingredients from the refrigerator.
+const INPUT: &str = "40\n";
+
+/// Steps before a run is stopped, as `pi.chef` never finishes.
+const MAX_STEPS: u64 = 200_000;
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let mut paths: Vec<_> = std::fs::read_dir("programs")
+        .expect("Failed to read programs/")
+        .map(|entry| entry.expect("Failed to read programs/").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "chef")
+        })
+        .collect();
+    paths.sort();
+
+    println!(
+        "{:<24} {:>14} {:>14} {:>8}",
+        "recipe", "tree", "vm", "speedup"
+    );
+    for path in paths {
+        let source = std::fs::read_to_string(&path).expect("Failed to read recipe");
+        let Ok(program) = parse(&source) else {
+            panic!("Failed to parse {}", path.display());
+        };
+        let bytecode = compile(&program);
+        let interpreter = || {
+            Interpreter::with_io(MemoryIo::new(INPUT))
+                .with_rng(NoShuffle)
+                .with_limits(Limits {
+                    max_steps: Some(MAX_STEPS),
+                    ..Limits::default()
+                })
+        };
+
+        let (tree, tree_served) = time(|| {
+            let mut interpreter = interpreter();
+            let result = interpreter.run_program(&program);
+            served(interpreter.io().output(), result.err())
+        });
+        let (vm, vm_served) = time(|| {
+            let mut interpreter = interpreter();
+            let result = interpreter.run_bytecode(&bytecode);
+            served(interpreter.io().output(), result.err())
+        });
+        assert_eq!(
+            tree_served,
+            vm_served,
+            "{} served differently",
+            path.display()
+        );
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "{name:<24} {:>14?} {:>14?} {:>7.2}x",
+            tree,
+            vm,
+            tree.as_secs_f64() / vm.as_secs_f64(),
+        );
+    }
+}
+
+/// The output of a run, followed by the error that stopped it if any.
+fn served(output: &str, error: Option<impl std::fmt::Display>) -> String {
+    match error {
+        Some(e) => format!("{output}\n{e}"),
+        None => output.to_string(),
+    }
+}
+
+/// Mean time of a run, after one run to warm up, with what the last run served.
+fn time(mut run: impl FnMut() -> String) -> (Duration, String) {
+    let mut served = run();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        served = run();
+    }
+    (start.elapsed() / RUNS, served)
+}
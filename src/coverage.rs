@@ -0,0 +1,72 @@
+//! Reports for `spatula run --coverage`.
+
+use spatula::interpreter::Coverage;
+
+/// Merges the coverage of this run with what the lcov tracefile at `tracefile` has for the recipe
+/// at `path`, writes it back with the records of other recipes left as they were, and prints a
+/// summary per recipe.
+pub fn report(path: &str, contents: &str, coverage: &mut Coverage, tracefile: &str) {
+    let mut others = String::new();
+    match std::fs::read_to_string(tracefile) {
+        Ok(previous) => {
+            for record in records(&previous) {
+                if !record.lines().any(|line| line == format!("SF:{path}")) {
+                    others.push_str(record);
+                    continue;
+                }
+                if let Err(e) = coverage.merge_lcov(record) {
+                    eprintln!("Failed to merge the coverage in {tracefile}: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            eprintln!("Failed to read {tracefile}: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    let mut lcov = others.into_bytes();
+    coverage
+        .write_lcov(&mut lcov, path)
+        .expect("Writing to memory cannot fail");
+    if let Err(e) = std::fs::write(tracefile, lcov) {
+        eprintln!("Failed to write {tracefile}: {e}");
+    }
+
+    let runs = coverage.recipes()[0].calls;
+    match runs {
+        1 => eprintln!("Coverage of 1 run:"),
+        runs => eprintln!("Coverage of {runs} runs:"),
+    }
+    for recipe in coverage.recipes() {
+        if recipe.calls == 0 {
+            eprintln!("    {}: never prepared", recipe.title);
+            continue;
+        }
+        let calls = match recipe.calls {
+            1 => "1 time".to_string(),
+            calls => format!("{calls} times"),
+        };
+        eprintln!(
+            "    {}: {} of {} steps run, prepared {calls}",
+            recipe.title,
+            recipe.covered(),
+            recipe.steps.len(),
+        );
+        for step in recipe.steps.iter().filter(|step| step.runs == 0) {
+            let text = &contents[step.span.into_range()];
+            // Loops span their whole body, so only their first step is shown
+            let text = text.split('.').next().unwrap_or(text);
+            eprintln!("        never run on line {}: {text}", step.line);
+        }
+    }
+}
+
+/// The records of an lcov tracefile, each ending with its `end_of_record` line.
+fn records(tracefile: &str) -> impl Iterator<Item = &str> {
+    tracefile
+        .split_inclusive("end_of_record\n")
+        .filter(|record| !record.trim().is_empty())
+}
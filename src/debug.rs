@@ -0,0 +1,255 @@
+//! The `spatula debug` command line.
+
+use std::io::Write;
+
+use ariadne::{sources, Color, Label, Report, ReportKind};
+use spatula::{
+    debugger::{Breakpoint, Debugger, Stop},
+    interpreter::{ChefIo, Frame, IngredientAmount, Interpreter, Kitchen, Number, StdIo},
+    parser::{ChefProgram, IngredientKind},
+};
+
+const HELP: &str = "Commands:
+    s, step              Run the next step, following `Serve with` into the recipe
+    n, next              Run the next step, including any recipe it calls for
+    o, out               Run until the current recipe ends
+    c, continue          Run until a breakpoint or the end of the main recipe
+    back                 Undo the last step, though not what it served or took as input
+    back <ingredient>    Go back to before the last step that gave an ingredient a value
+    back bowl <number>   Go back to before the last change to a mixing bowl
+    b, break <line>      Pause before the first step run on a line
+    b, break <recipe>    Pause whenever a sous-chef starts a recipe
+    d, delete <index>    Remove a breakpoint
+    breakpoints          List breakpoints
+    p, print [name]      Print ingredients, or one ingredient
+    bowls                Print mixing bowls
+    dishes               Print baking dishes
+    bt, backtrace        Print the recipes being prepared
+    w, where             Show the next step
+    q, quit              Stop debugging";
+
+/// How much memory steps that can be undone may take, in bytes.
+const JOURNAL_BYTES: usize = 64 << 20;
+
+/// Debugs `program` until the user quits, reading commands from STDIN.
+pub fn session<N: Number>(
+    program: &ChefProgram<'_>,
+    filename: &str,
+    contents: &str,
+    interpreter: Interpreter<StdIo, N>,
+) {
+    let interpreter = interpreter.with_journal(JOURNAL_BYTES);
+    let mut debugger = match Debugger::new(program, contents, interpreter) {
+        Ok(debugger) => debugger,
+        Err(e) => {
+            crate::report_runtime_error(filename, contents, &e);
+            return;
+        }
+    };
+    eprintln!("Debugging {filename}, type `help` for commands");
+    show_step(&debugger, filename, contents);
+
+    loop {
+        eprint!("(spatula) ");
+        std::io::stderr().flush().ok();
+        // STDIN is only locked while a command is read, as `Take` reads from it too
+        let mut line = String::new();
+        if !matches!(std::io::stdin().read_line(&mut line), Ok(1..)) {
+            return;
+        }
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+        let stop = match command {
+            "" => continue,
+            "s" | "step" => debugger.step_into(),
+            "n" | "next" => debugger.step_over(),
+            "o" | "out" => debugger.step_out(),
+            "c" | "continue" => debugger.resume(),
+            "back" => {
+                let found = match argument.split_once(' ') {
+                    _ if argument.is_empty() => debugger.step_back(),
+                    Some(("bowl", bowl)) => match bowl.trim().parse() {
+                        Ok(bowl) => debugger.back_to_bowl_change(bowl),
+                        Err(_) => {
+                            eprintln!("Expected a bowl number after back bowl");
+                            continue;
+                        }
+                    },
+                    _ => debugger.back_to_write(argument),
+                };
+                match found {
+                    true => show_step(&debugger, filename, contents),
+                    false => eprintln!("No step to go back to"),
+                }
+                continue;
+            }
+            "b" | "break" => {
+                let breakpoint = match argument.parse() {
+                    Ok(line) => Breakpoint::Line(line),
+                    Err(_) if argument.is_empty() => {
+                        eprintln!("Expected a line number or recipe title after break");
+                        continue;
+                    }
+                    Err(_) => Breakpoint::Recipe(argument.trim_end_matches('.').to_string()),
+                };
+                let index = debugger.add_breakpoint(breakpoint);
+                eprintln!("Breakpoint {index} set");
+                continue;
+            }
+            "d" | "delete" => {
+                match argument
+                    .parse()
+                    .ok()
+                    .and_then(|i| debugger.remove_breakpoint(i))
+                {
+                    Some(_) => eprintln!("Breakpoint {argument} removed"),
+                    None => eprintln!("No breakpoint {argument}"),
+                }
+                continue;
+            }
+            "breakpoints" => {
+                for (index, breakpoint) in debugger.breakpoints().iter().enumerate() {
+                    match breakpoint {
+                        Breakpoint::Line(line) => eprintln!("{index}: line {line}"),
+                        Breakpoint::Recipe(title) => eprintln!("{index}: recipe `{title}`"),
+                    }
+                }
+                continue;
+            }
+            "p" | "print" => {
+                match debugger.frames().last() {
+                    Some(frame) => print_ingredients(frame, argument),
+                    None => eprintln!("The main recipe has ended"),
+                }
+                continue;
+            }
+            "bowls" | "dishes" => {
+                let kitchen = match debugger.frames().last() {
+                    Some(frame) => frame.kitchen(),
+                    None => debugger
+                        .finished()
+                        .expect("Finished when no recipe is left"),
+                };
+                print_kitchen(kitchen, command == "bowls");
+                continue;
+            }
+            "bt" | "backtrace" => {
+                for frame in debugger.frames().iter().rev() {
+                    match frame.return_span() {
+                        Some(span) => eprintln!(
+                            "`{}`, called for on line {}",
+                            frame.recipe_title(),
+                            debugger.line(span.start)
+                        ),
+                        None => eprintln!("`{}`", frame.recipe_title()),
+                    }
+                }
+                continue;
+            }
+            "w" | "where" => {
+                show_step(&debugger, filename, contents);
+                continue;
+            }
+            "h" | "help" => {
+                eprintln!("{HELP}");
+                continue;
+            }
+            "q" | "quit" => return,
+            _ => {
+                eprintln!("Unknown command `{command}`, type `help` for commands");
+                continue;
+            }
+        };
+        match stop {
+            Ok(Stop::Paused) => show_step(&debugger, filename, contents),
+            Ok(Stop::Breakpoint(index)) => {
+                eprintln!("Breakpoint {index}");
+                show_step(&debugger, filename, contents);
+            }
+            Ok(Stop::Finished) => eprintln!("The main recipe has ended"),
+            Err(e) => {
+                crate::report_runtime_error(filename, contents, &e);
+                eprintln!("The step can be retried, or the kitchen inspected");
+            }
+        }
+    }
+}
+
+/// Highlights what the innermost recipe runs next.
+fn show_step<I, N: Number>(debugger: &Debugger<'_, '_, I, N>, filename: &str, contents: &str)
+where
+    I: ChefIo,
+{
+    let Some(frame) = debugger.frames().last() else {
+        eprintln!("The main recipe has ended");
+        return;
+    };
+    let Some(span) = debugger.span() else {
+        eprintln!("`{}` is done", frame.recipe_title());
+        return;
+    };
+    let filename = filename.to_string();
+    Report::build(
+        ReportKind::Custom("Next", Color::Cyan),
+        filename.clone(),
+        span.start,
+    )
+    .with_message(format!("Line {}", debugger.line(span.start)))
+    .with_label(
+        Label::new((filename.clone(), span.into_range()))
+            .with_message(format!("in `{}`", frame.recipe_title()))
+            .with_color(Color::Cyan),
+    )
+    .finish()
+    .eprint(sources([(filename, contents)]))
+    .unwrap();
+}
+
+/// Prints the ingredients of `frame`, or just the one called `name`.
+pub fn print_ingredients<N: Number>(frame: &Frame<N>, name: &str) {
+    let name = name.to_lowercase();
+    let mut found = false;
+    for (ingredient, kind, value) in frame.ingredients() {
+        if name.is_empty() || ingredient == name {
+            found = true;
+            match value {
+                Some(value) => eprintln!("{ingredient} = {}", describe(value, kind)),
+                None => eprintln!("{ingredient} has no value"),
+            }
+        }
+    }
+    if !found {
+        eprintln!("No ingredient `{name}` in `{}`", frame.recipe_title());
+    }
+}
+
+/// Prints the mixing bowls of `kitchen`, or its baking dishes.
+pub fn print_kitchen<N: Number>(kitchen: &Kitchen<N>, bowls: bool) {
+    let (name, contents) = match bowls {
+        true => ("Mixing bowl", kitchen.mixing_bowls()),
+        false => ("Baking dish", kitchen.baking_dishes()),
+    };
+    if contents.is_empty() {
+        eprintln!("{name} 1 is empty");
+    }
+    for (number, contents) in contents {
+        // Listed from the top down, the way they are taken out
+        let items: Vec<_> = contents.iter().rev().map(describe_amount).collect();
+        eprintln!("{name} {number}: [{}]", items.join(", "));
+    }
+}
+
+fn describe_amount<N: Number>(ingredient: &IngredientAmount<N>) -> String {
+    describe(ingredient.amount(), ingredient.kind())
+}
+
+/// A value, along with the character it is served as if it is liquid.
+fn describe<N: Number>(value: &N, kind: IngredientKind) -> String {
+    match (kind, value.to_u32().and_then(char::from_u32)) {
+        (IngredientKind::Wet, Some(c)) => format!("{value} {c:?}"),
+        (IngredientKind::Wet, None) => format!("{value} (liquid)"),
+        (IngredientKind::Dry, _) => value.to_string(),
+    }
+}
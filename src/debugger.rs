@@ -0,0 +1,345 @@
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    interpreter::{Change, ChefIo, Frame, Interpreter, Kitchen, Number, Status, TracedError},
+    parser::ChefProgram,
+};
+
+/// Where a [`Debugger`] pauses when it reaches it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The first method step run on a line, counting from 1.
+    Line(usize),
+    /// The first method step of a recipe, each time a sous-chef starts it. Titles are compared
+    /// ignoring case.
+    Recipe(String),
+}
+
+/// Why a [`Debugger`] paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested steps have been taken.
+    Paused,
+    /// Reached the breakpoint with this index.
+    Breakpoint(usize),
+    /// The main recipe has ended.
+    Finished,
+}
+
+/// Runs a program a step at a time, pausing at [`Breakpoint`]s.
+pub struct Debugger<'p, 'a, I, N> {
+    program: &'p ChefProgram<'a>,
+    source: &'p str,
+    interpreter: Interpreter<I, N>,
+    breakpoints: Vec<Breakpoint>,
+    finished: Option<Kitchen<N>>,
+}
+
+impl<'p, 'a, I: ChefIo, N: Number> Debugger<'p, 'a, I, N> {
+    /// Gets ready to debug `program`, parsed from `source`, paused before its first step.
+    pub fn new(
+        program: &'p ChefProgram<'a>,
+        source: &'p str,
+        mut interpreter: Interpreter<I, N>,
+    ) -> Result<Self, TracedError> {
+        if let Err(error) = interpreter.start(program) {
+            return Err(interpreter.traced(error));
+        }
+        Ok(Self {
+            program,
+            source,
+            interpreter,
+            breakpoints: Vec::new(),
+            finished: None,
+        })
+    }
+
+    pub fn program(&self) -> &'p ChefProgram<'a> {
+        self.program
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<I, N> {
+        &self.interpreter
+    }
+
+    /// The recipes being prepared, the main recipe first. Empty once the main recipe has ended.
+    pub fn frames(&self) -> &[Frame<N>] {
+        self.interpreter.frames()
+    }
+
+    /// The head chef's kitchen as it was left, once the main recipe has ended.
+    pub fn finished(&self) -> Option<&Kitchen<N>> {
+        self.finished.as_ref()
+    }
+
+    /// Span of what the innermost recipe runs next.
+    pub fn span(&self) -> Option<SimpleSpan> {
+        self.frames().last()?.span(self.program)
+    }
+
+    /// The line of `offset` in the source, counting from 1.
+    pub fn line(&self, offset: usize) -> usize {
+        self.source[..offset].matches('\n').count() + 1
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint, returning its index.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    /// Takes one step, following `Serve with` into the sous-chef's recipe.
+    pub fn step_into(&mut self) -> Result<Stop, TracedError> {
+        match self.step()? {
+            Some(_) => Ok(Stop::Paused),
+            None => Ok(Stop::Finished),
+        }
+    }
+
+    /// Takes one step, running any sous-chef it calls for until they are done.
+    pub fn step_over(&mut self) -> Result<Stop, TracedError> {
+        let depth = self.frames().len();
+        self.run_while(|frames| frames > depth)
+    }
+
+    /// Runs until the innermost recipe has ended.
+    pub fn step_out(&mut self) -> Result<Stop, TracedError> {
+        let depth = self.frames().len();
+        self.run_while(|frames| frames >= depth)
+    }
+
+    /// Runs until a breakpoint is reached or the main recipe ends.
+    pub fn resume(&mut self) -> Result<Stop, TracedError> {
+        self.run_while(|_| true)
+    }
+
+    /// Undoes the last step, returning `false` if there is none in the interpreter's
+    /// [`Journal`](crate::interpreter::Journal).
+    pub fn step_back(&mut self) -> bool {
+        self.back_until(|_| true)
+    }
+
+    /// Steps back to just before the last step that gave the ingredient `name` a value, in any
+    /// recipe. Returns `false`, without stepping back, if no journaled step did.
+    pub fn back_to_write(&mut self, name: &str) -> bool {
+        self.back_until(|change| change.writes(name))
+    }
+
+    /// Steps back to just before the last step that changed the mixing bowl with ordinal
+    /// `bowl`, in any recipe. Returns `false`, without stepping back, if no journaled step did.
+    pub fn back_to_bowl_change(&mut self, bowl: usize) -> bool {
+        self.back_until(|change| change.changes_bowl(bowl))
+    }
+
+    /// Undoes steps until one that made a change that is `found`.
+    fn back_until(&mut self, found: impl Fn(&Change<N>) -> bool) -> bool {
+        let Some(journal) = self.interpreter.journal() else {
+            return false;
+        };
+        let journaled = journal.entries().iter();
+        if !journaled.flat_map(|entry| &entry.changes).any(&found) {
+            return false;
+        }
+        while let Some(entry) = self.interpreter.step_back() {
+            self.finished = None;
+            if entry.changes.iter().any(&found) {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Steps at least once, then for as long as `running` holds for the number of frames.
+    fn run_while(&mut self, running: impl Fn(usize) -> bool) -> Result<Stop, TracedError> {
+        loop {
+            let Some(hit) = self.step()? else {
+                return Ok(Stop::Finished);
+            };
+            if let Some(index) = hit {
+                return Ok(Stop::Breakpoint(index));
+            }
+            if !running(self.frames().len()) {
+                return Ok(Stop::Paused);
+            }
+        }
+    }
+
+    /// Takes a step, returning `None` once the main recipe has ended, or else the breakpoint reached.
+    fn step(&mut self) -> Result<Option<Option<usize>>, TracedError> {
+        if self.finished.is_some() {
+            return Ok(None);
+        }
+        let before = self.location();
+        match self.interpreter.step(self.program) {
+            Ok(Status::Running) => Ok(Some(self.breakpoint(before))),
+            Ok(Status::Finished(kitchen)) => {
+                self.finished = Some(kitchen);
+                Ok(None)
+            }
+            Err(error) => Err(self.interpreter.traced(error)),
+        }
+    }
+
+    /// The number of frames and the line the innermost recipe is on.
+    fn location(&self) -> (usize, Option<usize>) {
+        let line = self.span().map(|span| self.line(span.start));
+        (self.frames().len(), line)
+    }
+
+    fn breakpoint(&self, before: (usize, Option<usize>)) -> Option<usize> {
+        let (depth, line) = self.location();
+        let entered = depth > before.0;
+        let title = self.frames().last()?.recipe_title();
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| match breakpoint {
+                Breakpoint::Line(at) => line == Some(*at) && (entered || before != (depth, line)),
+                Breakpoint::Recipe(recipe) => entered && recipe.eq_ignore_ascii_case(title),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::MemoryIo;
+
+    const SOURCE: &str = r#"Sponge Cake.
+
+Ingredients.
+2 eggs
+1 g sugar
+
+Method.
+Put eggs into mixing bowl.
+Serve with icing.
+Put sugar into mixing bowl.
+
+Icing.
+
+Ingredients.
+3 g sugar
+
+Method.
+Put sugar into mixing bowl.
+Put sugar into mixing bowl."#;
+
+    fn debug<'p>(program: &'p ChefProgram<'p>) -> Debugger<'p, 'p, MemoryIo, i64> {
+        let interpreter = Interpreter::with_io(MemoryIo::default()).with_journal(1 << 16);
+        let Ok(debugger) = Debugger::new(program, SOURCE, interpreter) else {
+            panic!("Failed to start recipe");
+        };
+        debugger
+    }
+
+    fn line(debugger: &Debugger<'_, '_, MemoryIo, i64>) -> Option<usize> {
+        debugger.span().map(|span| debugger.line(span.start))
+    }
+
+    #[test]
+    fn test_stepping() {
+        let program = crate::parser::parse_recipe(SOURCE);
+
+        let mut debugger = debug(&program);
+        assert_eq!(line(&debugger), Some(8));
+        assert_eq!(debugger.step_into(), Ok(Stop::Paused));
+        assert_eq!(debugger.step_into(), Ok(Stop::Paused));
+        assert_eq!(debugger.frames().len(), 2);
+        assert_eq!(line(&debugger), Some(18));
+        assert_eq!(debugger.step_out(), Ok(Stop::Paused));
+        assert_eq!(debugger.frames().len(), 1);
+        assert_eq!(line(&debugger), Some(10));
+
+        let mut debugger = debug(&program);
+        debugger.step_into().unwrap();
+        assert_eq!(debugger.step_over(), Ok(Stop::Paused));
+        assert_eq!(line(&debugger), Some(10));
+        let (_, _, sugar) = debugger.frames()[0].ingredients()[1];
+        assert_eq!(sugar, Some(&1));
+        let bowl: Vec<_> = debugger.frames()[0].kitchen().mixing_bowl(1).to_vec();
+        assert_eq!(
+            bowl.iter().map(|v| *v.amount()).collect::<Vec<_>>(),
+            [2, 2, 3, 3]
+        );
+        assert_eq!(debugger.step_over(), Ok(Stop::Paused));
+        assert_eq!(debugger.step_over(), Ok(Stop::Finished));
+        assert!(debugger.finished().is_some());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let program = crate::parser::parse_recipe(SOURCE);
+
+        let mut debugger = debug(&program);
+        debugger.add_breakpoint(Breakpoint::Line(10));
+        debugger.add_breakpoint(Breakpoint::Recipe("icing".to_string()));
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(1)));
+        assert_eq!(debugger.frames().len(), 2);
+        // Breakpoints inside a sous-chef's recipe stop stepping over it
+        let mut stepping = debug(&program);
+        stepping.add_breakpoint(Breakpoint::Line(19));
+        stepping.step_into().unwrap();
+        assert_eq!(stepping.step_over(), Ok(Stop::Breakpoint(0)));
+
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(0)));
+        assert_eq!(line(&debugger), Some(10));
+        assert_eq!(debugger.remove_breakpoint(0), Some(Breakpoint::Line(10)));
+        assert_eq!(debugger.resume(), Ok(Stop::Finished));
+    }
+
+    #[test]
+    fn test_stepping_back() {
+        let program = crate::parser::parse_recipe(SOURCE);
+        let bowl = |debugger: &Debugger<'_, '_, MemoryIo, i64>| {
+            let kitchen = match debugger.frames().last() {
+                Some(frame) => frame.kitchen(),
+                None => debugger.finished().unwrap(),
+            };
+            kitchen
+                .mixing_bowl(1)
+                .iter()
+                .map(|v| *v.amount())
+                .collect::<Vec<_>>()
+        };
+
+        let mut debugger = debug(&program);
+        assert!(!debugger.step_back());
+        assert_eq!(debugger.resume(), Ok(Stop::Finished));
+        assert_eq!(bowl(&debugger), [2, 2, 3, 3, 1]);
+
+        // Back into the main recipe after it ended
+        assert!(debugger.step_back());
+        assert_eq!(line(&debugger), None);
+        assert!(debugger.step_back());
+        assert_eq!(line(&debugger), Some(10));
+        assert_eq!(bowl(&debugger), [2, 2, 3, 3]);
+
+        // Back to the sous-chef handing over their bowl, then to their last `Put`
+        assert!(debugger.back_to_bowl_change(1));
+        assert_eq!(debugger.frames().len(), 2);
+        assert_eq!(line(&debugger), None);
+        assert_eq!(bowl(&debugger), [2, 3, 3]);
+        assert!(debugger.back_to_bowl_change(1));
+        assert_eq!(line(&debugger), Some(19));
+        assert_eq!(bowl(&debugger), [2, 3]);
+
+        assert!(!debugger.back_to_write("flour"));
+        assert!(debugger.back_to_bowl_change(1));
+        assert!(debugger.back_to_bowl_change(1));
+        assert_eq!(debugger.frames().len(), 1);
+        assert_eq!(line(&debugger), Some(8));
+        assert_eq!(bowl(&debugger), Vec::<i64>::new());
+
+        // Replays the same way
+        assert_eq!(debugger.resume(), Ok(Stop::Finished));
+        assert_eq!(bowl(&debugger), [2, 2, 3, 3, 1]);
+    }
+}
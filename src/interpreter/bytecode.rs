@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use chumsky::span::SimpleSpan;
+
+use super::Operation;
+use crate::parser::{ChefProgram, ChefRecipe, Ingredient, IngredientKind, Instruction, Spanned};
+
+type Recipe<'a> = ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>;
+
+/// Index of a recipe in a [`Bytecode`], where the main recipe is `0`.
+pub type RecipeId = usize;
+
+/// An ingredient resolved to its slot in the recipe, with the name the step used for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot<'a> {
+    pub index: usize,
+    pub name: &'a str,
+}
+
+/// A mixing bowl resolved to its index in the kitchen, with the ordinal the step used for it,
+/// which is `0` if omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bowl {
+    pub index: usize,
+    pub ordinal: usize,
+}
+
+/// A method step compiled to a flat instruction. Loops and `Set aside` jump to indices in the
+/// code of their recipe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op<'a> {
+    Take(Slot<'a>),
+    Put(Slot<'a>, Bowl),
+    Fold(Slot<'a>, Bowl),
+    /// `Add`, `Remove`, `Combine` or `Divide`.
+    Arithmetic(Operation, Slot<'a>, Bowl),
+    AddDryIngredients(Bowl),
+    Liquefy(Slot<'a>),
+    LiquefyContents(Bowl),
+    Stir(Bowl, usize),
+    StirIngredient(Slot<'a>, Bowl),
+    Mix(Bowl),
+    Clean(Bowl),
+    /// Pours a bowl into the baking dish with the given index.
+    Pour(Bowl, usize),
+    /// The verb opening a loop, which jumps to `end` if the ingredient is zero and otherwise runs
+    /// the body that follows.
+    Loop {
+        ingredient: Slot<'a>,
+        end: usize,
+    },
+    /// The `until` closing a loop, which takes one from `until` if given and jumps back to `body`
+    /// unless the ingredient of the loop is zero.
+    Until {
+        ingredient: Slot<'a>,
+        until: Option<Slot<'a>>,
+        body: usize,
+    },
+    /// `Set aside` in a loop, which jumps past the `until` of the innermost loop.
+    Jump(usize),
+    Call(RecipeId),
+    /// `Serve with` naming a recipe that is not in the program.
+    UndefinedRecipe(&'a str),
+    Serves(usize),
+    Refrigerate(Option<usize>),
+    /// `Set aside` outside of any loop, which ends the recipe without serving.
+    Return,
+    /// The end of the method, serving the given number of dishes if the recipe says how many it
+    /// serves. Unlike every other op, it is not a step of its own.
+    End(Option<usize>),
+}
+
+/// A recipe compiled to [`Op`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledRecipe<'a> {
+    pub title: &'a str,
+    /// Lowercase name of the ingredient in each slot, with the kind it was last declared with, or
+    /// `None` if a step names it without it being declared.
+    pub slots: Vec<(String, Option<IngredientKind>)>,
+    /// Initial values in the order they are measured out: slot, value, kind and the span of the
+    /// declaration.
+    pub measures: Vec<(usize, usize, IngredientKind, SimpleSpan)>,
+    pub code: Vec<Op<'a>>,
+    /// Span of the step each op was compiled from.
+    pub spans: Vec<SimpleSpan>,
+}
+
+/// A program compiled with [`compile`], to be run with
+/// [`Interpreter::run_bytecode`](super::Interpreter::run_bytecode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bytecode<'a> {
+    recipes: Vec<CompiledRecipe<'a>>,
+}
+
+impl<'a> Bytecode<'a> {
+    /// Every recipe by [`RecipeId`], the main recipe first.
+    pub fn recipes(&self) -> &[CompiledRecipe<'a>] {
+        &self.recipes
+    }
+}
+
+/// Compiles a program to flat bytecode, with ingredients resolved to slots and recipes to
+/// [`RecipeId`]s.
+///
+/// Steps naming an ingredient or recipe that does not exist still compile, and fail when they
+/// are run, as they do in the tree-walking interpreter.
+pub fn compile<'a>(program: &ChefProgram<'a>) -> Bytecode<'a> {
+    let mut auxiliary: Vec<_> = program.auxilary.iter().collect();
+    auxiliary.sort_by_key(|(key, _)| key.as_str());
+    let ids: HashMap<&str, RecipeId> = auxiliary
+        .iter()
+        .enumerate()
+        .map(|(i, (key, _))| (key.as_str(), i + 1))
+        .collect();
+    let recipes = std::iter::once(&program.main)
+        .chain(auxiliary.into_iter().map(|(_, recipe)| recipe))
+        .map(|recipe| Compiler::new(recipe, &ids).compile(recipe))
+        .collect();
+    Bytecode { recipes }
+}
+
+struct Compiler<'a, 'i> {
+    ids: &'i HashMap<&'i str, RecipeId>,
+    slots: Vec<(String, Option<IngredientKind>)>,
+    by_name: HashMap<String, usize>,
+    code: Vec<Op<'a>>,
+    spans: Vec<SimpleSpan>,
+    /// `Set aside` jumps waiting for the end of each loop being compiled, innermost last
+    set_asides: Vec<Vec<usize>>,
+}
+
+impl<'a, 'i> Compiler<'a, 'i> {
+    fn new(recipe: &Recipe<'a>, ids: &'i HashMap<&'i str, RecipeId>) -> Self {
+        let mut compiler = Self {
+            ids,
+            slots: Vec::new(),
+            by_name: HashMap::new(),
+            code: Vec::new(),
+            spans: Vec::new(),
+            set_asides: Vec::new(),
+        };
+        // A later declaration of the same ingredient decides its kind
+        for Spanned(ingredient, _) in &recipe.ingredients {
+            let index = compiler.slot_index(ingredient.name);
+            compiler.slots[index].1 = Some(ingredient.kind);
+        }
+        compiler
+    }
+
+    fn compile(mut self, recipe: &Recipe<'a>) -> CompiledRecipe<'a> {
+        let measures = recipe
+            .ingredients
+            .iter()
+            .filter_map(|Spanned(ingredient, span)| {
+                let value = ingredient.initial_value?;
+                let slot = self.by_name[&ingredient.name.to_lowercase()];
+                Some((slot, value, ingredient.kind, *span))
+            })
+            .collect();
+        self.instructions(&recipe.instructions);
+        let (diners, span) = match &recipe.serves {
+            Some(Spanned(diners, span)) => (Some(*diners), *span),
+            None => (
+                None,
+                recipe
+                    .instructions
+                    .last()
+                    .map_or(SimpleSpan::new(0, 0), |i| i.1),
+            ),
+        };
+        self.emit(Op::End(diners), span);
+        CompiledRecipe {
+            title: recipe.title,
+            slots: self.slots,
+            measures,
+            code: self.code,
+            spans: self.spans,
+        }
+    }
+
+    fn instructions(&mut self, instructions: &[Spanned<Instruction<'a>>]) {
+        for Spanned(instruction, span) in instructions {
+            let span = *span;
+            let op = match instruction {
+                Instruction::Take(name) => Op::Take(self.slot(name)),
+                Instruction::Put(name, bowl) => Op::Put(self.slot(name), bowl_at(*bowl)),
+                Instruction::Fold(name, bowl) => Op::Fold(self.slot(name), bowl_at(*bowl)),
+                Instruction::Add(name, bowl) => self.arithmetic(Operation::Add, name, *bowl),
+                Instruction::Remove(name, bowl) => {
+                    self.arithmetic(Operation::Subtract, name, *bowl)
+                }
+                Instruction::Combine(name, bowl) => {
+                    self.arithmetic(Operation::Multiply, name, *bowl)
+                }
+                Instruction::Divide(name, bowl) => self.arithmetic(Operation::Divide, name, *bowl),
+                Instruction::AddDryIngredients(bowl) => Op::AddDryIngredients(bowl_at(*bowl)),
+                Instruction::Liquefy(name) => Op::Liquefy(self.slot(name)),
+                Instruction::LiquefyContents(bowl) => Op::LiquefyContents(bowl_at(*bowl)),
+                Instruction::Stir(bowl, minutes) => Op::Stir(bowl_at(*bowl), *minutes),
+                Instruction::StirIngredient(name, bowl) => {
+                    Op::StirIngredient(self.slot(name), bowl_at(*bowl))
+                }
+                Instruction::Mix(bowl) => Op::Mix(bowl_at(*bowl)),
+                Instruction::Clean(bowl) => Op::Clean(bowl_at(*bowl)),
+                Instruction::Pour(bowl, dish) => Op::Pour(bowl_at(*bowl), dish.saturating_sub(1)),
+                Instruction::VerbLoop(verb_loop) => {
+                    let ingredient = self.slot(verb_loop.ingredient);
+                    let until = verb_loop.until_ingredient.map(|name| self.slot(name));
+                    let head = self.emit(Op::Loop { ingredient, end: 0 }, span);
+                    self.set_asides.push(Vec::new());
+                    self.instructions(&verb_loop.instructions);
+                    let body = head + 1;
+                    // The `until` is a step with the span of the whole loop
+                    let end = self.emit(
+                        Op::Until {
+                            ingredient,
+                            until,
+                            body,
+                        },
+                        span,
+                    ) + 1;
+                    self.code[head] = Op::Loop { ingredient, end };
+                    for jump in self.set_asides.pop().expect("Pushed for this loop") {
+                        self.code[jump] = Op::Jump(end);
+                    }
+                    continue;
+                }
+                Instruction::SetAside => match self.set_asides.last_mut() {
+                    Some(jumps) => {
+                        jumps.push(self.code.len());
+                        Op::Jump(0)
+                    }
+                    None => Op::Return,
+                },
+                Instruction::ServeWith(name) => match self.ids.get(name.to_lowercase().as_str()) {
+                    Some(id) => Op::Call(*id),
+                    None => Op::UndefinedRecipe(name),
+                },
+                Instruction::Refrigerate(hours) => Op::Refrigerate(*hours),
+                Instruction::Serves(diners) => Op::Serves(*diners),
+            };
+            self.emit(op, span);
+        }
+    }
+
+    fn arithmetic(&mut self, operation: Operation, name: &'a str, bowl: usize) -> Op<'a> {
+        Op::Arithmetic(operation, self.slot(name), bowl_at(bowl))
+    }
+
+    /// Adds an op, returning its index.
+    fn emit(&mut self, op: Op<'a>, span: SimpleSpan) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    fn slot(&mut self, name: &'a str) -> Slot<'a> {
+        Slot {
+            index: self.slot_index(name),
+            name,
+        }
+    }
+
+    /// The slot of an ingredient, giving it an undeclared one if it has none yet.
+    fn slot_index(&mut self, name: &str) -> usize {
+        let name = name.to_lowercase();
+        if let Some(&index) = self.by_name.get(&name) {
+            return index;
+        }
+        self.slots.push((name.clone(), None));
+        self.by_name.insert(name, self.slots.len() - 1);
+        self.slots.len() - 1
+    }
+}
+
+fn bowl_at(ordinal: usize) -> Bowl {
+    Bowl {
+        index: ordinal.saturating_sub(1),
+        ordinal,
+    }
+}
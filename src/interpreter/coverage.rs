@@ -0,0 +1,400 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::Write,
+};
+
+use chumsky::span::SimpleSpan;
+
+use super::{Frame, Observer, Position};
+use crate::parser::{ChefProgram, Instruction, Spanned};
+
+/// How often a method step ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoveredStep {
+    pub span: SimpleSpan,
+    /// Line of the step in the source, counting from 1.
+    pub line: usize,
+    pub runs: u64,
+}
+
+/// Every method step of a recipe, including those in loop bodies, and how often each ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoveredRecipe {
+    pub title: String,
+    /// Line of the title in the source, counting from 1.
+    pub line: usize,
+    /// Times the recipe was started.
+    pub calls: u64,
+    /// Steps in the order they appear in the source.
+    pub steps: Vec<CoveredStep>,
+}
+
+impl CoveredRecipe {
+    /// How many of the steps ran at least once.
+    pub fn covered(&self) -> usize {
+        self.steps.iter().filter(|step| step.runs > 0).count()
+    }
+}
+
+/// Why an lcov record could not be merged into a [`Coverage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoverageError {
+    /// A line of the record, counting from 1, that could not be read.
+    Malformed { line: usize, reason: String },
+    /// The record names a recipe the program does not have.
+    UnknownRecipe(String),
+    /// The record counts a step the program does not have on a line.
+    UnknownStep { line: usize },
+}
+
+impl Display for CoverageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoverageError::Malformed { line, reason } => {
+                write!(f, "Malformed lcov record on line {line}: {reason}")
+            }
+            CoverageError::UnknownRecipe(title) => {
+                write!(
+                    f,
+                    "Coverage is recorded for a recipe `{title}` that is not in the program"
+                )
+            }
+            CoverageError::UnknownStep { line } => write!(
+                f,
+                "Coverage is recorded for a step on line {line} that is not in the program"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CoverageError {}
+
+/// Counts how often each method step of a program runs, as an [`Observer`].
+///
+/// Coverage of several runs is merged by writing it as an lcov tracefile with
+/// [`write_lcov`](Self::write_lcov) and reading it into the next run with
+/// [`merge_lcov`](Self::merge_lcov). Lines are counted as often as their most run step, and every
+/// step is written as a branch of its line, so steps sharing a line are told apart.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    /// The main recipe first, then the auxiliary recipes in the order they appear in the source
+    recipes: Vec<CoveredRecipe>,
+    /// Index in `recipes` of each auxiliary recipe by its key in the program
+    keys: HashMap<String, usize>,
+    /// Recipe and step of each step span
+    steps: HashMap<SimpleSpan, (usize, usize)>,
+    /// Spans of the steps on each line, in order
+    lines: BTreeMap<usize, Vec<SimpleSpan>>,
+    started: bool,
+}
+
+impl Coverage {
+    /// Covers `program`, parsed from `source`, with no steps run yet.
+    pub fn new(program: &ChefProgram, source: &str) -> Self {
+        let starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let line = |span: SimpleSpan| starts.partition_point(|&start| start <= span.start);
+
+        let mut auxiliary: Vec<_> = program.auxilary.iter().collect();
+        auxiliary.sort_by_key(|(_, recipe)| title_line(source, recipe.title));
+        let mut coverage = Self {
+            recipes: Vec::new(),
+            keys: HashMap::new(),
+            steps: HashMap::new(),
+            lines: BTreeMap::new(),
+            started: false,
+        };
+        let recipes = std::iter::once((None, &program.main)).chain(
+            auxiliary
+                .into_iter()
+                .map(|(key, recipe)| (Some(key), recipe)),
+        );
+        for (key, recipe) in recipes {
+            let index = coverage.recipes.len();
+            let mut spans = Vec::new();
+            steps(&recipe.instructions, &mut spans);
+            spans.sort_by_key(|span| span.start);
+            for (step, &span) in spans.iter().enumerate() {
+                coverage.steps.insert(span, (index, step));
+                coverage.lines.entry(line(span)).or_default().push(span);
+            }
+            if let Some(key) = key {
+                coverage.keys.insert(key.clone(), index);
+            }
+            coverage.recipes.push(CoveredRecipe {
+                title: recipe.title.to_string(),
+                line: title_line(source, recipe.title),
+                calls: 0,
+                steps: spans
+                    .into_iter()
+                    .map(|span| CoveredStep {
+                        span,
+                        line: line(span),
+                        runs: 0,
+                    })
+                    .collect(),
+            });
+        }
+        for spans in coverage.lines.values_mut() {
+            spans.sort_by_key(|span| span.start);
+        }
+        coverage
+    }
+
+    /// The main recipe first, then the auxiliary recipes in the order they appear in the source.
+    pub fn recipes(&self) -> &[CoveredRecipe] {
+        &self.recipes
+    }
+
+    /// Writes the coverage as an lcov record for the source file at `path`.
+    ///
+    /// Recipes are written as functions, and method steps as branches of the line they are on.
+    pub fn write_lcov(&self, mut out: impl Write, path: &str) -> std::io::Result<()> {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{path}")?;
+        for recipe in &self.recipes {
+            writeln!(out, "FN:{},{}", recipe.line, function(&recipe.title))?;
+        }
+        for recipe in &self.recipes {
+            writeln!(out, "FNDA:{},{}", recipe.calls, function(&recipe.title))?;
+        }
+        let prepared = self.recipes.iter().filter(|recipe| recipe.calls > 0);
+        writeln!(out, "FNF:{}", self.recipes.len())?;
+        writeln!(out, "FNH:{}", prepared.count())?;
+
+        let lines: Vec<(usize, Vec<u64>)> = self
+            .lines
+            .iter()
+            .map(|(&line, spans)| (line, spans.iter().map(|span| self.runs(span)).collect()))
+            .collect();
+        for (line, runs) in &lines {
+            let reached = runs.iter().any(|&runs| runs > 0);
+            for (branch, runs) in runs.iter().enumerate() {
+                match reached {
+                    true => writeln!(out, "BRDA:{line},0,{branch},{runs}")?,
+                    false => writeln!(out, "BRDA:{line},0,{branch},-")?,
+                }
+            }
+        }
+        let runs = lines.iter().flat_map(|(_, runs)| runs);
+        writeln!(out, "BRF:{}", runs.clone().count())?;
+        writeln!(out, "BRH:{}", runs.filter(|&&runs| runs > 0).count())?;
+        for (line, runs) in &lines {
+            writeln!(out, "DA:{line},{}", runs.iter().max().unwrap_or(&0))?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        let hit = lines.iter().filter(|(_, runs)| runs.iter().any(|&r| r > 0));
+        writeln!(out, "LH:{}", hit.count())?;
+        writeln!(out, "end_of_record")
+    }
+
+    /// Adds the counts of an lcov record written by [`write_lcov`](Self::write_lcov) for the same
+    /// program. Nothing is added if the record does not fit the program.
+    pub fn merge_lcov(&mut self, record: &str) -> Result<(), CoverageError> {
+        let mut calls = Vec::new();
+        let mut runs = Vec::new();
+        for (number, line) in record.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            let malformed = |reason: &str| CoverageError::Malformed {
+                line: number,
+                reason: reason.to_string(),
+            };
+            if let Some(rest) = line.strip_prefix("FNDA:") {
+                let (count, name) = rest.split_once(',').ok_or_else(|| malformed("no name"))?;
+                let count: u64 = count.parse().map_err(|_| malformed("invalid count"))?;
+                let recipe = self
+                    .recipes
+                    .iter()
+                    .position(|recipe| function(&recipe.title) == name)
+                    .ok_or_else(|| CoverageError::UnknownRecipe(name.to_string()))?;
+                calls.push((recipe, count));
+            } else if let Some(rest) = line.strip_prefix("BRDA:") {
+                let fields: Vec<_> = rest.split(',').collect();
+                let [line, _, branch, taken] = fields[..] else {
+                    return Err(malformed("expected 4 fields"));
+                };
+                let line: usize = line.parse().map_err(|_| malformed("invalid line"))?;
+                let branch: usize = branch.parse().map_err(|_| malformed("invalid branch"))?;
+                let taken: u64 = match taken {
+                    "-" => 0,
+                    taken => taken.parse().map_err(|_| malformed("invalid count"))?,
+                };
+                let span = self
+                    .lines
+                    .get(&line)
+                    .and_then(|spans| spans.get(branch))
+                    .ok_or(CoverageError::UnknownStep { line })?;
+                runs.push((self.steps[span], taken));
+            }
+        }
+        for (recipe, count) in calls {
+            self.recipes[recipe].calls += count;
+        }
+        for ((recipe, step), taken) in runs {
+            self.recipes[recipe].steps[step].runs += taken;
+        }
+        Ok(())
+    }
+
+    fn runs(&self, span: &SimpleSpan) -> u64 {
+        let (recipe, step) = self.steps[span];
+        self.recipes[recipe].steps[step].runs
+    }
+}
+
+impl<N> Observer<N> for Coverage {
+    fn before(&mut self, _frame: &Frame<N>, position: &Position) {
+        if !self.started {
+            self.started = true;
+            self.recipes[0].calls += 1;
+        }
+        if let Position::Instruction(Spanned(_, span)) = position {
+            if let Some(&(recipe, step)) = self.steps.get(span) {
+                self.recipes[recipe].steps[step].runs += 1;
+            }
+        }
+    }
+
+    fn enter(&mut self, frame: &Frame<N>) {
+        if let Some(&recipe) = frame.recipe_key().and_then(|key| self.keys.get(key)) {
+            self.recipes[recipe].calls += 1;
+        }
+    }
+}
+
+/// Adds the spans of `instructions` and the steps of their loop bodies to `spans`.
+fn steps(instructions: &[Spanned<Instruction>], spans: &mut Vec<SimpleSpan>) {
+    for Spanned(instruction, span) in instructions {
+        spans.push(*span);
+        if let Instruction::VerbLoop(verb_loop) = instruction {
+            steps(&verb_loop.instructions, spans);
+        }
+    }
+}
+
+/// Line of the title of a recipe, which is a line of its own ending in a full stop.
+fn title_line(source: &str, title: &str) -> usize {
+    source
+        .lines()
+        .position(|line| line.trim().strip_suffix('.') == Some(title))
+        .map_or(1, |line| line + 1)
+}
+
+/// Name of a recipe as an lcov function, where commas separate fields.
+fn function(title: &str) -> String {
+    title.replace(',', ";")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::interpreter::{Interpreter, MemoryIo};
+
+    #[test]
+    fn test_coverage() {
+        let source = r#"
+Pancakes.
+
+Ingredients.
+1 egg
+0 g flour
+
+Method.
+Put egg into mixing bowl. Serve with syrup.
+Sift the flour. Put flour into mixing bowl. Sift until sifted.
+Pour contents of the mixing bowl into the baking dish.
+
+Serves 1.
+
+Syrup.
+
+Ingredients.
+2 g sugar
+
+Method.
+Put sugar into mixing bowl.
+
+Jam.
+
+Ingredients.
+3 g strawberries
+
+Method.
+Put strawberries into mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let run = |previous: Option<&str>| {
+            let coverage = Rc::new(RefCell::new(Coverage::new(&program, source)));
+            if let Some(previous) = previous {
+                coverage.borrow_mut().merge_lcov(previous).unwrap();
+            }
+            Interpreter::with_io(MemoryIo::default())
+                .with_observer(Rc::clone(&coverage))
+                .run_program(&program)
+                .unwrap();
+            let mut lcov = Vec::new();
+            coverage
+                .borrow()
+                .write_lcov(&mut lcov, "pancakes.chef")
+                .unwrap();
+            let summary: Vec<_> = coverage
+                .borrow()
+                .recipes()
+                .iter()
+                .map(|recipe| {
+                    let runs: Vec<_> = recipe.steps.iter().map(|step| step.runs).collect();
+                    (recipe.title.clone(), recipe.line, recipe.calls, runs)
+                })
+                .collect();
+            (String::from_utf8(lcov).unwrap(), summary)
+        };
+
+        let (first, _) = run(None);
+        let (second, summary) = run(Some(&first));
+        assert_eq!(
+            summary,
+            [
+                ("Pancakes".to_string(), 1, 2, vec![2, 2, 2, 0, 2]),
+                ("Syrup".to_string(), 14, 2, vec![2]),
+                ("Jam".to_string(), 22, 0, vec![0]),
+            ]
+        );
+        let expected = "TN:
+SF:pancakes.chef
+FN:1,Pancakes
+FN:14,Syrup
+FN:22,Jam
+FNDA:2,Pancakes
+FNDA:2,Syrup
+FNDA:0,Jam
+FNF:3
+FNH:2
+BRDA:8,0,0,2
+BRDA:8,0,1,2
+BRDA:9,0,0,2
+BRDA:9,0,1,0
+BRDA:10,0,0,2
+BRDA:20,0,0,2
+BRDA:28,0,0,-
+BRF:7
+BRH:5
+DA:8,2
+DA:9,2
+DA:10,2
+DA:20,2
+DA:28,0
+LF:5
+LH:4
+end_of_record
+";
+        assert_eq!(second, expected);
+
+        let mut coverage = Coverage::new(&program, source);
+        let error = coverage.merge_lcov("BRDA:12,0,0,1");
+        assert_eq!(error, Err(CoverageError::UnknownStep { line: 12 }));
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt::Display;
+
+use chumsky::span::SimpleSpan;
+
+use super::{ArithmeticError, Limit};
+use crate::SpatulaError;
+
+/// Why a recipe could not be prepared, with the span of the method step that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// A step needed an ingredient from a mixing bowl, given by the ordinal used in the recipe, that was empty.
+    EmptyBowl {
+        bowl: usize,
+        span: SimpleSpan,
+    },
+    /// An ingredient that is not in the ingredient list.
+    UndefinedIngredient {
+        name: String,
+        span: SimpleSpan,
+    },
+    /// An ingredient that was declared without a value and has not been given one since.
+    UninitializedIngredient {
+        name: String,
+        span: SimpleSpan,
+    },
+    /// A `Serve with` naming a recipe that is not in the program.
+    UndefinedRecipe {
+        name: String,
+        span: SimpleSpan,
+    },
+    /// An initial value that does not fit the number type.
+    InitialValueTooLarge {
+        value: usize,
+        span: SimpleSpan,
+    },
+    /// Stirring for a number of minutes that is negative or too large.
+    InvalidMinutes {
+        value: String,
+        span: SimpleSpan,
+    },
+    DivisionByZero {
+        span: SimpleSpan,
+    },
+    Overflow {
+        span: SimpleSpan,
+    },
+    Underflow {
+        span: SimpleSpan,
+    },
+    /// A liquid ingredient whose value is not a Unicode scalar value.
+    InvalidCodepoint {
+        value: String,
+        span: SimpleSpan,
+    },
+    /// `Take` found no more input.
+    EndOfInput {
+        span: SimpleSpan,
+    },
+    ReadInput {
+        reason: String,
+        span: SimpleSpan,
+    },
+    WriteOutput {
+        reason: String,
+        span: SimpleSpan,
+    },
+    LimitExceeded {
+        limit: Limit,
+        span: SimpleSpan,
+    },
+}
+
+impl RuntimeError {
+    pub(crate) fn arithmetic(error: ArithmeticError, span: SimpleSpan) -> Self {
+        match error {
+            ArithmeticError::Overflow => RuntimeError::Overflow { span },
+            ArithmeticError::Underflow => RuntimeError::Underflow { span },
+            ArithmeticError::DivisionByZero => RuntimeError::DivisionByZero { span },
+        }
+    }
+
+    /// The span of the method step, ingredient or statement that failed.
+    pub fn span(&self) -> SimpleSpan {
+        match self {
+            RuntimeError::EmptyBowl { span, .. }
+            | RuntimeError::UndefinedIngredient { span, .. }
+            | RuntimeError::UninitializedIngredient { span, .. }
+            | RuntimeError::UndefinedRecipe { span, .. }
+            | RuntimeError::InitialValueTooLarge { span, .. }
+            | RuntimeError::InvalidMinutes { span, .. }
+            | RuntimeError::DivisionByZero { span }
+            | RuntimeError::Overflow { span }
+            | RuntimeError::Underflow { span }
+            | RuntimeError::InvalidCodepoint { span, .. }
+            | RuntimeError::EndOfInput { span }
+            | RuntimeError::ReadInput { span, .. }
+            | RuntimeError::WriteOutput { span, .. }
+            | RuntimeError::LimitExceeded { span, .. } => *span,
+        }
+    }
+
+    /// A code identifying the kind of error, which stays the same between releases.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::EmptyBowl { .. } => "R0001",
+            RuntimeError::UndefinedIngredient { .. } => "R0002",
+            RuntimeError::UninitializedIngredient { .. } => "R0003",
+            RuntimeError::UndefinedRecipe { .. } => "R0004",
+            RuntimeError::InitialValueTooLarge { .. } => "R0005",
+            RuntimeError::InvalidMinutes { .. } => "R0006",
+            RuntimeError::DivisionByZero { .. } => "R0007",
+            RuntimeError::Overflow { .. } => "R0008",
+            RuntimeError::Underflow { .. } => "R0009",
+            RuntimeError::InvalidCodepoint { .. } => "R0010",
+            RuntimeError::EndOfInput { .. } => "R0011",
+            RuntimeError::ReadInput { .. } => "R0012",
+            RuntimeError::WriteOutput { .. } => "R0013",
+            RuntimeError::LimitExceeded { .. } => "R0014",
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::EmptyBowl { .. } => write!(f, "Bowl is empty"),
+            RuntimeError::UndefinedIngredient { .. } => write!(f, "Ingredient does not exist"),
+            RuntimeError::UninitializedIngredient { name, .. } => {
+                write!(f, "Ingredient `{name}` has no value")
+            }
+            RuntimeError::UndefinedRecipe { name, .. } => write!(f, "Recipe `{name}` not found"),
+            RuntimeError::InitialValueTooLarge { value, .. } => {
+                write!(f, "Initial value {value} is too large")
+            }
+            RuntimeError::InvalidMinutes { value, .. } => {
+                write!(f, "Cannot stir for {value} minutes")
+            }
+            RuntimeError::DivisionByZero { .. } => write!(f, "{}", ArithmeticError::DivisionByZero),
+            RuntimeError::Overflow { .. } => write!(f, "{}", ArithmeticError::Overflow),
+            RuntimeError::Underflow { .. } => write!(f, "{}", ArithmeticError::Underflow),
+            RuntimeError::InvalidCodepoint { value, .. } => {
+                write!(f, "{value} is not a valid Unicode character")
+            }
+            RuntimeError::EndOfInput { .. } => write!(f, "Ran out of input"),
+            RuntimeError::ReadInput { reason, .. } => write!(f, "Failed to read input: {reason}"),
+            RuntimeError::WriteOutput { reason, .. } => {
+                write!(f, "Failed to write output: {reason}")
+            }
+            RuntimeError::LimitExceeded { limit, .. } => write!(f, "{limit}"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<RuntimeError> for SpatulaError {
+    fn from(error: RuntimeError) -> Self {
+        SpatulaError::new(error.to_string(), error.span())
+    }
+}
+
+/// A recipe that was being prepared when an error happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub title: String,
+    /// Span of the `Serve with` step that called for the recipe, `None` for the main recipe.
+    pub call_span: Option<SimpleSpan>,
+}
+
+/// A [`RuntimeError`] with the chain of `Serve with` steps that led to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedError {
+    pub error: RuntimeError,
+    /// The recipes being prepared, the one that failed first and the main recipe last.
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl TracedError {
+    pub fn span(&self) -> SimpleSpan {
+        self.error.span()
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.error.code()
+    }
+}
+
+impl Display for TracedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for TracedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<TracedError> for SpatulaError {
+    fn from(error: TracedError) -> Self {
+        error.error.into()
+    }
+}
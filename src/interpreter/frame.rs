@@ -0,0 +1,189 @@
+use chumsky::span::SimpleSpan;
+
+use super::{EvalContext, Kitchen};
+use crate::parser::{
+    ChefProgram, ChefRecipe, Ingredient, IngredientKind, Instruction, Spanned, VerbLoop,
+};
+
+type Recipe<'a> = ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>;
+
+/// A recipe being prepared, by the head chef for the main recipe or by a sous-chef for an auxiliary one.
+///
+/// Frames live on the heap, so recipes can call each other as deep as the [`Limits`](super::Limits) allow.
+#[derive(Debug, Clone)]
+pub struct Frame<N> {
+    /// Key of the recipe in [`ChefProgram::auxilary`], `None` for the main recipe
+    recipe: Option<String>,
+    title: String,
+    return_span: Option<SimpleSpan>,
+    pub(crate) ctx: EvalContext<N>,
+    /// Index of the next instruction of the method, followed by one for the body of each loop entered
+    pc: Vec<usize>,
+}
+
+/// Where a frame is in its recipe.
+#[derive(Debug, Clone, Copy)]
+pub enum Position<'p, 'a> {
+    /// About to run an instruction.
+    Instruction(&'p Spanned<Instruction<'a>>),
+    /// Reached the `until` at the end of a loop body.
+    EndOfLoop(&'p Spanned<Instruction<'a>>, &'p VerbLoop<'a>),
+    /// Ran out of instructions, with the recipe's `Serves` statement if it has one.
+    EndOfRecipe(Option<&'p Spanned<usize>>),
+}
+
+impl<N> Frame<N> {
+    pub(crate) fn new(
+        recipe: Option<String>,
+        title: &str,
+        return_span: Option<SimpleSpan>,
+        ctx: EvalContext<N>,
+    ) -> Self {
+        Self {
+            recipe,
+            title: title.to_string(),
+            return_span,
+            ctx,
+            pc: vec![0],
+        }
+    }
+
+    /// Title of the recipe being prepared.
+    pub fn recipe_title(&self) -> &str {
+        &self.title
+    }
+
+    /// Span of the `Serve with` step that handed the recipe to a sous-chef, `None` for the main recipe.
+    pub fn return_span(&self) -> Option<SimpleSpan> {
+        self.return_span
+    }
+
+    pub fn kitchen(&self) -> &Kitchen<N> {
+        &self.ctx.kitchen
+    }
+
+    /// Every ingredient in the recipe by lowercase name, with its value if it has one.
+    pub fn ingredients(&self) -> Vec<(&str, IngredientKind, Option<&N>)> {
+        let mut ingredients: Vec<_> = self
+            .ctx
+            .kinds
+            .iter()
+            .map(|(name, kind)| {
+                let value = self.ctx.values.get(name);
+                (
+                    name.as_str(),
+                    value.map_or(*kind, |v| v.kind),
+                    value.map(|v| &v.amount),
+                )
+            })
+            .collect();
+        ingredients.sort_by_key(|(name, ..)| *name);
+        ingredients
+    }
+
+    /// Span of what runs next: a method step, the loop whose `until` is reached, or the recipe's
+    /// `Serves` once the method is done.
+    pub fn span(&self, program: &ChefProgram) -> Option<SimpleSpan> {
+        match self.position(program) {
+            Position::Instruction(Spanned(_, span)) | Position::EndOfLoop(Spanned(_, span), _) => {
+                Some(*span)
+            }
+            Position::EndOfRecipe(serves) => serves.map(|Spanned(_, span)| *span),
+        }
+    }
+
+    /// Key of the recipe in [`ChefProgram::auxilary`], `None` for the main recipe.
+    pub(crate) fn recipe_key(&self) -> Option<&str> {
+        self.recipe.as_deref()
+    }
+
+    pub(crate) fn pc(&self) -> &[usize] {
+        &self.pc
+    }
+
+    /// Continues from `pc` instead of where the frame was.
+    pub(crate) fn set_pc(&mut self, pc: Vec<usize>) {
+        self.pc = pc;
+    }
+
+    /// Whether the frame is at a step that exists in `program`.
+    pub(crate) fn fits(&self, program: &ChefProgram) -> bool {
+        let mut instructions = match &self.recipe {
+            Some(key) => match program.auxilary.get(key) {
+                Some(recipe) => recipe.instructions.as_slice(),
+                None => return false,
+            },
+            None => program.main.instructions.as_slice(),
+        };
+        let Some((&next, loops)) = self.pc.split_last() else {
+            return false;
+        };
+        for &entered in loops {
+            match entered.checked_sub(1).and_then(|i| instructions.get(i)) {
+                Some(Spanned(Instruction::VerbLoop(verb_loop), _)) => {
+                    instructions = &verb_loop.instructions
+                }
+                _ => return false,
+            }
+        }
+        next <= instructions.len()
+    }
+
+    pub(crate) fn into_kitchen(self) -> Kitchen<N> {
+        self.ctx.kitchen
+    }
+
+    pub(crate) fn recipe<'p, 'a>(&self, program: &'p ChefProgram<'a>) -> &'p Recipe<'a> {
+        match &self.recipe {
+            Some(key) => &program.auxilary[key],
+            None => &program.main,
+        }
+    }
+
+    /// What the frame runs next.
+    pub fn position<'p, 'a>(&self, program: &'p ChefProgram<'a>) -> Position<'p, 'a> {
+        let mut instructions = self.recipe(program).instructions.as_slice();
+        let mut current_loop = None;
+        for &next in &self.pc[..self.pc.len() - 1] {
+            // Entering a loop moves past it before its body starts
+            let instruction = &instructions[next - 1];
+            let Instruction::VerbLoop(verb_loop) = &instruction.0 else {
+                unreachable!("Only loops have a body");
+            };
+            instructions = &verb_loop.instructions;
+            current_loop = Some((instruction, verb_loop));
+        }
+
+        let next = self.pc[self.pc.len() - 1];
+        match (instructions.get(next), current_loop) {
+            (Some(instruction), _) => Position::Instruction(instruction),
+            (None, Some((instruction, verb_loop))) => Position::EndOfLoop(instruction, verb_loop),
+            (None, None) => Position::EndOfRecipe(self.recipe(program).serves.as_ref()),
+        }
+    }
+
+    /// Moves on to the next instruction at the current level.
+    pub(crate) fn advance(&mut self) {
+        *self.pc.last_mut().expect("A frame is always in its method") += 1;
+    }
+
+    /// Starts the body of the loop that was just advanced past.
+    pub(crate) fn enter_loop(&mut self) {
+        self.pc.push(0);
+    }
+
+    /// Goes back to the start of the body of the innermost loop.
+    pub(crate) fn repeat_loop(&mut self) {
+        *self.pc.last_mut().expect("A frame is always in its method") = 0;
+    }
+
+    /// Continues after the innermost loop, returning `false` if not in a loop.
+    pub(crate) fn exit_loop(&mut self) -> bool {
+        if self.pc.len() > 1 {
+            self.pc.pop();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,19 @@
+/// How `Take` reads ingredients from the refrigerator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub end_of_input: EndOfInput,
+    /// Takes the next Unicode character of input instead of a number on a line of its own.
+    pub chars: bool,
+}
+
+/// What `Take` does when there is no more input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EndOfInput {
+    /// Fail with [`RuntimeError::EndOfInput`](super::RuntimeError::EndOfInput).
+    #[default]
+    Error,
+    /// Take a zero.
+    Zero,
+    /// End the recipe that is taking input, like `Refrigerate` without serving.
+    Stop,
+}
@@ -0,0 +1,77 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, Write},
+};
+
+/// Where a recipe takes its ingredients from the refrigerator and serves its dishes to.
+pub trait ChefIo {
+    /// Reads the next line of input for `Take`, or `None` when there is no more input.
+    fn read_line(&mut self) -> std::io::Result<Option<String>>;
+
+    /// Writes served dishes.
+    fn write(&mut self, output: &str) -> std::io::Result<()>;
+
+    /// Called when a line of input could not be used, before the next line is read.
+    fn reject_input(&mut self, _line: &str, _reason: &str) {}
+}
+
+/// Reads from STDIN and serves to STDOUT, prompting for input and reporting rejected input on STDERR.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+impl ChefIo for StdIo {
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        eprint!("> ");
+        let mut buf = String::new();
+        match std::io::stdin().lock().read_line(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf)),
+        }
+    }
+
+    fn write(&mut self, output: &str) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn reject_input(&mut self, line: &str, reason: &str) {
+        eprintln!("Invalid input `{}`: {reason}", line.trim_end());
+    }
+}
+
+/// Takes input from a script and captures everything that is served.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryIo {
+    input: VecDeque<String>,
+    output: String,
+}
+
+impl MemoryIo {
+    /// Each line of `input` is handed out in turn, with its line ending.
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.split_inclusive('\n').map(str::to_string).collect(),
+            output: String::new(),
+        }
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn into_output(self) -> String {
+        self.output
+    }
+}
+
+impl ChefIo for MemoryIo {
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write(&mut self, output: &str) -> std::io::Result<()> {
+        self.output.push_str(output);
+        Ok(())
+    }
+}
@@ -0,0 +1,460 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use chumsky::span::SimpleSpan;
+
+use super::{Frame, IngredientAmount, Number, Position};
+use crate::parser::{ChefProgram, Instruction, Spanned};
+
+/// Where ingredients are kept in a kitchen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    MixingBowl,
+    BakingDish,
+}
+
+/// A change a step made, holding what was there before so it can be undone.
+///
+/// `frame` is the index of the recipe being prepared that changed, `0` for the main recipe, and
+/// bowls and dishes are numbered by their ordinal. Contents are listed bottom first.
+#[derive(Debug, Clone)]
+pub enum Change<N> {
+    /// An ingredient was given a value, replacing `old` if it had one.
+    Ingredient {
+        frame: usize,
+        name: String,
+        old: Option<IngredientAmount<N>>,
+        new: IngredientAmount<N>,
+    },
+    /// Ingredients were put on top of a bowl or dish.
+    Push {
+        frame: usize,
+        container: Container,
+        number: usize,
+        amounts: Vec<IngredientAmount<N>>,
+    },
+    /// Ingredients were taken off the top of a bowl or dish.
+    Pop {
+        frame: usize,
+        container: Container,
+        number: usize,
+        amounts: Vec<IngredientAmount<N>>,
+    },
+    /// The top of a bowl was rearranged by stirring or mixing.
+    Reorder {
+        frame: usize,
+        container: Container,
+        number: usize,
+        old: Vec<IngredientAmount<N>>,
+        new: Vec<IngredientAmount<N>>,
+    },
+    /// A sous-chef started preparing a recipe.
+    Call { frame: usize, title: String },
+    /// A recipe ended, as it was when it did. Its frame is no longer used by the interpreter, so
+    /// it is kept here rather than copied.
+    Return { frame: usize, ended: Box<Frame<N>> },
+}
+
+impl<N> Change<N> {
+    /// Whether the change gave the ingredient `name` a value, ignoring case.
+    pub fn writes(&self, name: &str) -> bool {
+        matches!(self, Change::Ingredient { name: written, .. } if written.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the change touched the mixing bowl with ordinal `number`.
+    pub fn changes_bowl(&self, bowl: usize) -> bool {
+        match self {
+            Change::Push {
+                container, number, ..
+            }
+            | Change::Pop {
+                container, number, ..
+            }
+            | Change::Reorder {
+                container, number, ..
+            } => *container == Container::MixingBowl && *number == bowl.max(1),
+            _ => false,
+        }
+    }
+}
+
+impl<N: Number> Change<N> {
+    /// Roughly how many bytes the change takes up.
+    fn size(&self) -> usize {
+        let amounts = |amounts: &[IngredientAmount<N>]| size_of_val(amounts);
+        size_of::<Self>()
+            + match self {
+                Change::Ingredient { name, .. } | Change::Call { title: name, .. } => name.len(),
+                Change::Push { amounts: a, .. } | Change::Pop { amounts: a, .. } => amounts(a),
+                Change::Reorder { old, new, .. } => amounts(old) + amounts(new),
+                Change::Return { ended, .. } => frame_size(ended),
+            }
+    }
+}
+
+/// Everything one step changed.
+#[derive(Debug, Clone)]
+pub struct Entry<N> {
+    /// Title of the recipe that took the step.
+    pub recipe: String,
+    /// Span of the step, `None` for the end of a recipe without `Serves`.
+    pub span: Option<SimpleSpan>,
+    pub changes: Vec<Change<N>>,
+    /// Index of the frame that took the step, and where it was in its method
+    frame: usize,
+    pc: Vec<usize>,
+    rng: Option<u64>,
+    /// How many characters were read but not taken, and the one the step took if any
+    unread: usize,
+    taken: Option<char>,
+    /// Bowls and dishes first used by the step, with the index of their frame
+    created: Vec<(usize, Container, usize)>,
+}
+
+/// The changes made by the most recent steps, oldest first, for stepping backwards.
+///
+/// Only the last steps whose changes fit in `capacity` bytes are kept, as estimated from the
+/// ingredients they held. Served output and input taken from the refrigerator are not journaled,
+/// so stepping back does not take them back.
+#[derive(Debug, Clone)]
+pub struct Journal<N> {
+    capacity: usize,
+    size: usize,
+    entries: VecDeque<Entry<N>>,
+    /// The step being taken, until it is done
+    step: Option<Entry<N>>,
+}
+
+impl<N: Number> Journal<N> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            entries: VecDeque::new(),
+            step: None,
+        }
+    }
+
+    pub fn entries(&self) -> &VecDeque<Entry<N>> {
+        &self.entries
+    }
+
+    /// Roughly how many bytes the journaled steps take up.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Starts journaling the next step of the innermost recipe of `frames`, with `unread`
+    /// characters read but not taken.
+    pub(crate) fn begin(
+        &mut self,
+        program: &ChefProgram,
+        frames: &[Frame<N>],
+        rng: Option<u64>,
+        unread: usize,
+    ) {
+        let frame = frames.last().expect("No recipe is being prepared");
+        let index = frames.len() - 1;
+        let mut created = vec![];
+        if let Position::Instruction(Spanned(instruction, _)) = frame.position(program) {
+            if let Some((container, number)) = container(instruction) {
+                let kitchen = frame.kitchen();
+                let used = match container {
+                    Container::MixingBowl => kitchen.has_bowl(number),
+                    Container::BakingDish => kitchen.has_dish(number),
+                };
+                if !used {
+                    created.push((index, container, number.max(1)));
+                }
+            }
+        }
+        self.step = Some(Entry {
+            recipe: frame.recipe_title().to_string(),
+            span: frame.span(program),
+            changes: vec![],
+            frame: index,
+            pc: frame.pc().to_vec(),
+            rng,
+            unread,
+            taken: None,
+            created,
+        });
+    }
+
+    /// Adds a change made by the step being taken.
+    pub(crate) fn change(&mut self, mut change: Change<N>) {
+        // An omitted ordinal refers to the first bowl or dish
+        if let Change::Push { number, .. }
+        | Change::Pop { number, .. }
+        | Change::Reorder { number, .. } = &mut change
+        {
+            *number = (*number).max(1);
+        }
+        if let Some(step) = &mut self.step {
+            step.changes.push(change);
+        }
+    }
+
+    /// Notes that the step being taken started using a bowl or dish of the frame at `index`.
+    pub(crate) fn created(&mut self, index: usize, container: Container, number: usize) {
+        if let Some(step) = &mut self.step {
+            step.created.push((index, container, number.max(1)));
+        }
+    }
+
+    /// Notes that the step being taken took the character `taken` from those read.
+    pub(crate) fn take(&mut self, taken: char) {
+        if let Some(step) = &mut self.step {
+            step.taken = Some(taken);
+        }
+    }
+
+    /// Journals the step being taken, dropping the oldest steps that no longer fit.
+    pub(crate) fn commit(&mut self) {
+        let Some(step) = self.step.take() else {
+            return;
+        };
+        self.size += step.size();
+        self.entries.push_back(step);
+        while self.size > self.capacity {
+            let Some(oldest) = self.entries.pop_front() else {
+                break;
+            };
+            self.size -= oldest.size();
+        }
+    }
+
+    /// Removes the last step from the journal.
+    pub(crate) fn pop(&mut self) -> Option<Entry<N>> {
+        let entry = self.entries.pop_back()?;
+        self.size -= entry.size();
+        Some(entry)
+    }
+}
+
+impl<N: Number> Entry<N> {
+    /// Undoes the step on `frames` and the characters read but not taken, returning the state of
+    /// the generator mixing bowls before it.
+    pub(crate) fn undo(
+        &self,
+        frames: &mut Vec<Frame<N>>,
+        unread: &mut VecDeque<char>,
+    ) -> Option<u64> {
+        for change in self.changes.iter().rev() {
+            match change {
+                Change::Ingredient {
+                    frame, name, old, ..
+                } => {
+                    let values = &mut frames[*frame].ctx.values;
+                    match old {
+                        Some(old) => values.insert(name.clone(), old.clone()),
+                        None => values.remove(name),
+                    };
+                }
+                Change::Push {
+                    frame,
+                    container,
+                    number,
+                    amounts,
+                } => {
+                    let contents = contents(&mut frames[*frame], *container, *number);
+                    contents.truncate(contents.len() - amounts.len());
+                }
+                Change::Pop {
+                    frame,
+                    container,
+                    number,
+                    amounts,
+                } => contents(&mut frames[*frame], *container, *number)
+                    .extend(amounts.iter().cloned()),
+                Change::Reorder {
+                    frame,
+                    container,
+                    number,
+                    old,
+                    new,
+                } => {
+                    let contents = contents(&mut frames[*frame], *container, *number);
+                    contents.truncate(contents.len() - new.len());
+                    contents.extend(old.iter().cloned());
+                }
+                Change::Call { .. } => {
+                    frames.pop();
+                }
+                Change::Return { ended, .. } => frames.push(Frame::clone(ended)),
+            }
+        }
+        for &(frame, container, number) in &self.created {
+            let kitchen = &mut frames[frame].ctx.kitchen;
+            match container {
+                Container::MixingBowl if kitchen.mixing_bowl(number).is_empty() => {
+                    kitchen.discard_bowl(number)
+                }
+                Container::BakingDish if kitchen.baking_dish(number).is_empty() => {
+                    kitchen.discard_dish(number)
+                }
+                _ => {}
+            }
+        }
+        frames[self.frame].set_pc(self.pc.clone());
+        // Taking a character leaves the rest of those read before, followed by any line read
+        if let Some(taken) = self.taken {
+            unread.truncate(self.unread.saturating_sub(1));
+            if self.unread > 0 {
+                unread.push_front(taken);
+            }
+        }
+        self.rng
+    }
+
+    /// Roughly how many bytes the entry takes up.
+    fn size(&self) -> usize {
+        size_of::<Self>()
+            + self.recipe.len()
+            + size_of_val(self.pc.as_slice())
+            + size_of_val(self.created.as_slice())
+            + self.changes.iter().map(Change::size).sum::<usize>()
+    }
+}
+
+fn contents<N: Number>(
+    frame: &mut Frame<N>,
+    container: Container,
+    number: usize,
+) -> &mut Vec<IngredientAmount<N>> {
+    match container {
+        Container::MixingBowl => frame.ctx.kitchen.bowl_mut(number),
+        Container::BakingDish => frame.ctx.kitchen.dish_mut(number),
+    }
+}
+
+/// The bowl or dish `instruction` puts ingredients in, takes them from or rearranges, which it
+/// starts using if it was not used before.
+pub(super) fn container(instruction: &Instruction) -> Option<(Container, usize)> {
+    match instruction {
+        Instruction::Put(_, bowl)
+        | Instruction::Fold(_, bowl)
+        | Instruction::Add(_, bowl)
+        | Instruction::Remove(_, bowl)
+        | Instruction::Combine(_, bowl)
+        | Instruction::Divide(_, bowl)
+        | Instruction::AddDryIngredients(bowl)
+        | Instruction::LiquefyContents(bowl)
+        | Instruction::Stir(bowl, _)
+        | Instruction::StirIngredient(_, bowl)
+        | Instruction::Mix(bowl)
+        | Instruction::Clean(bowl) => Some((Container::MixingBowl, *bowl)),
+        Instruction::Pour(_, dish) => Some((Container::BakingDish, *dish)),
+        _ => None,
+    }
+}
+
+/// Roughly how many bytes a frame takes up, counting its ingredients and what is in its kitchen.
+fn frame_size<N: Number>(frame: &Frame<N>) -> usize {
+    let ingredient = size_of::<String>() + size_of::<IngredientAmount<N>>();
+    size_of::<Frame<N>>()
+        + frame.ctx.kinds.len() * ingredient
+        + frame.kitchen().items() * size_of::<IngredientAmount<N>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Input, Interpreter, Kitchen, MemoryIo};
+
+    #[test]
+    fn test_step_back() {
+        let source = r#"Journal Jam.
+
+Ingredients.
+1 g sugar
+2 g berries
+3 g pectin
+
+Method.
+Put sugar into mixing bowl. Put berries into mixing bowl. Put pectin into mixing bowl. Stir for 2 minutes. Fold sugar into mixing bowl. Pour contents of the mixing bowl into the baking dish."#;
+        let program = crate::parser::parse_recipe(source);
+        // Room for the last four steps, as measured by a journal with room for every step
+        let mut interpreter = Interpreter::with_io(MemoryIo::default()).with_journal(usize::MAX);
+        interpreter.start(&program).unwrap();
+        for _ in 0..6 {
+            interpreter.step(&program).unwrap();
+        }
+        let entries = interpreter.journal().unwrap().entries();
+        let capacity = entries.iter().skip(2).map(Entry::size).sum();
+        let mut interpreter = Interpreter::with_io(MemoryIo::default()).with_journal(capacity);
+        interpreter.start(&program).unwrap();
+        let state = |interpreter: &Interpreter<MemoryIo>| -> (Option<i64>, Kitchen) {
+            let frame = &interpreter.frames()[0];
+            let sugar = frame.ingredients()[2].2.copied();
+            (sugar, frame.kitchen().clone())
+        };
+        let mut states = vec![state(&interpreter)];
+        for _ in 0..6 {
+            interpreter.step(&program).unwrap();
+            states.push(state(&interpreter));
+        }
+
+        let journal = interpreter.journal().unwrap();
+        assert_eq!(journal.entries().len(), 4);
+        assert_eq!(journal.size(), capacity);
+        let stir = &journal.entries()[1].changes;
+        assert!(
+            matches!(&stir[..], [Change::Reorder { old, new, .. }] if old.len() == 3 && new.len() == 3)
+        );
+        let fold = &journal.entries()[2].changes;
+        assert!(fold.iter().any(|change| change.writes("Sugar")));
+        assert!(matches!(fold[1], Change::Pop { .. }));
+        let pour = &journal.entries()[3].changes;
+        assert!(matches!(
+            &pour[..],
+            [Change::Push {
+                container: Container::BakingDish,
+                ..
+            }]
+        ));
+
+        for expected in states.iter().rev().skip(1).take(4) {
+            assert!(interpreter.step_back().is_some());
+            assert_eq!(&state(&interpreter), expected);
+        }
+        assert!(interpreter.step_back().is_none());
+    }
+
+    #[test]
+    fn test_step_back_input() {
+        let source = r#"Letter Soup.
+
+Ingredients.
+letter
+
+Method.
+Take letter from refrigerator. Put letter into mixing bowl. Liquefy letter. Take letter from refrigerator. Clean mixing bowl."#;
+        let program = crate::parser::parse_recipe(source);
+        let input = Input {
+            chars: true,
+            ..Input::default()
+        };
+        let mut interpreter = Interpreter::with_io(MemoryIo::new("ab\n"))
+            .with_input(input)
+            .with_journal(usize::MAX);
+        interpreter.start(&program).unwrap();
+        let state = |interpreter: &Interpreter<MemoryIo>| {
+            let frame = &interpreter.frames()[0];
+            let (_, kind, letter) = frame.ingredients()[0];
+            let unread: String = interpreter.unread.iter().collect();
+            (kind, letter.copied(), frame.kitchen().clone(), unread)
+        };
+        let mut states = vec![state(&interpreter)];
+        for _ in 0..5 {
+            interpreter.step(&program).unwrap();
+            states.push(state(&interpreter));
+        }
+        assert_eq!(states[1].3, "b\n");
+        assert_eq!(states[4].3, "\n");
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(interpreter.step_back().is_some());
+            assert_eq!(&state(&interpreter), expected);
+        }
+    }
+}
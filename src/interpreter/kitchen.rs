@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use super::IngredientAmount;
+
+/// The mixing bowls and baking dishes a chef works with.
+///
+/// Bowls and dishes are referred to by the ordinal used in the recipe, so `1` is the first one.
+/// An omitted ordinal is parsed as `0`, which also refers to the first one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Kitchen<N = i64> {
+    bowls: HashMap<usize, Vec<IngredientAmount<N>>>,
+    dishes: HashMap<usize, Vec<IngredientAmount<N>>>,
+}
+
+impl<N: Clone> Kitchen<N> {
+    pub fn new() -> Self {
+        Self {
+            bowls: HashMap::new(),
+            dishes: HashMap::new(),
+        }
+    }
+
+    /// Contents of the nth mixing bowl, bottom first.
+    pub fn mixing_bowl(&self, bowl: usize) -> &[IngredientAmount<N>] {
+        self.bowls
+            .get(&index(bowl))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Contents of the nth baking dish, bottom first.
+    pub fn baking_dish(&self, dish: usize) -> &[IngredientAmount<N>] {
+        self.dishes
+            .get(&index(dish))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// All mixing bowls that have been used, ordered by ordinal.
+    pub fn mixing_bowls(&self) -> Vec<(usize, &[IngredientAmount<N>])> {
+        sorted(&self.bowls)
+    }
+
+    /// All baking dishes that have been used, ordered by ordinal.
+    pub fn baking_dishes(&self) -> Vec<(usize, &[IngredientAmount<N>])> {
+        sorted(&self.dishes)
+    }
+
+    /// Number of ingredients in all bowls and dishes together.
+    pub fn items(&self) -> usize {
+        self.bowls
+            .values()
+            .chain(self.dishes.values())
+            .map(Vec::len)
+            .sum()
+    }
+
+    pub(crate) fn bowl_mut(&mut self, bowl: usize) -> &mut Vec<IngredientAmount<N>> {
+        self.bowls.entry(index(bowl)).or_default()
+    }
+
+    pub(crate) fn dish_mut(&mut self, dish: usize) -> &mut Vec<IngredientAmount<N>> {
+        self.dishes.entry(index(dish)).or_default()
+    }
+
+    /// Whether the nth mixing bowl has been used.
+    pub(crate) fn has_bowl(&self, bowl: usize) -> bool {
+        self.bowls.contains_key(&index(bowl))
+    }
+
+    pub(crate) fn has_dish(&self, dish: usize) -> bool {
+        self.dishes.contains_key(&index(dish))
+    }
+
+    /// Forgets a mixing bowl, so it is no longer listed as used.
+    pub(crate) fn discard_bowl(&mut self, bowl: usize) {
+        self.bowls.remove(&index(bowl));
+    }
+
+    pub(crate) fn discard_dish(&mut self, dish: usize) {
+        self.dishes.remove(&index(dish));
+    }
+
+    /// Copies the contents of a mixing bowl on top of a baking dish. The bowl is left as is.
+    pub(crate) fn pour(&mut self, bowl: usize, dish: usize) {
+        let contents = self.mixing_bowl(bowl).to_vec();
+        self.dish_mut(dish).extend(contents);
+    }
+
+    /// Empties a mixing bowl, returning what was in it. Baking dishes are never cleaned, only
+    /// served.
+    pub(crate) fn clean(&mut self, bowl: usize) -> Vec<IngredientAmount<N>> {
+        std::mem::take(self.bowl_mut(bowl))
+    }
+
+    /// Contents of the first `diners` baking dishes in serving order: the first dish from the top
+    /// down, then the second, and so on.
+    pub(crate) fn servings(&self, diners: usize) -> impl Iterator<Item = &IngredientAmount<N>> {
+        (0..diners)
+            .filter_map(|dish| self.dishes.get(&dish))
+            .flat_map(|dish| dish.iter().rev())
+    }
+
+    /// Empties the first `diners` baking dishes, returning their contents in the order of
+    /// [`servings`](Self::servings).
+    pub(crate) fn serve(&mut self, diners: usize) -> Vec<IngredientAmount<N>> {
+        let mut served = vec![];
+        for dish in 0..diners {
+            if let Some(dish) = self.dishes.get_mut(&dish) {
+                served.extend(std::mem::take(dish).into_iter().rev());
+            }
+        }
+        served
+    }
+
+    /// Hands back the first mixing bowl, as a sous-chef does when finishing an auxiliary recipe.
+    pub(crate) fn into_first_bowl(mut self) -> Vec<IngredientAmount<N>> {
+        self.bowls.remove(&0).unwrap_or_default()
+    }
+}
+
+impl<N: Clone> Default for Kitchen<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn index(ordinal: usize) -> usize {
+    ordinal.saturating_sub(1)
+}
+
+fn sorted<N>(
+    containers: &HashMap<usize, Vec<IngredientAmount<N>>>,
+) -> Vec<(usize, &[IngredientAmount<N>])> {
+    let mut containers = containers
+        .iter()
+        .map(|(index, contents)| (index + 1, contents.as_slice()))
+        .collect::<Vec<_>>();
+    containers.sort_by_key(|(ordinal, _)| *ordinal);
+    containers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::IngredientKind;
+
+    fn dry(amounts: &[i64]) -> Vec<IngredientAmount> {
+        amounts
+            .iter()
+            .map(|amount| IngredientAmount::new(*amount, IngredientKind::Dry))
+            .collect()
+    }
+
+    #[test]
+    fn test_omitted_ordinal_is_first() {
+        let mut kitchen = Kitchen::new();
+        kitchen.bowl_mut(0).extend(dry(&[1, 2]));
+        assert_eq!(kitchen.mixing_bowl(1), dry(&[1, 2]));
+        assert_eq!(kitchen.mixing_bowls(), vec![(1, dry(&[1, 2]).as_slice())]);
+    }
+
+    #[test]
+    fn test_pour_copies_onto_dish() {
+        let mut kitchen = Kitchen::new();
+        kitchen.bowl_mut(1).extend(dry(&[1, 2]));
+        kitchen.pour(1, 2);
+        kitchen.bowl_mut(1).extend(dry(&[3]));
+        kitchen.pour(1, 2);
+
+        assert_eq!(kitchen.mixing_bowl(1), dry(&[1, 2, 3]));
+        assert_eq!(kitchen.baking_dish(2), dry(&[1, 2, 1, 2, 3]));
+        assert_eq!(kitchen.baking_dish(1), &[]);
+    }
+
+    #[test]
+    fn test_clean_leaves_dishes() {
+        let mut kitchen = Kitchen::new();
+        kitchen.bowl_mut(1).extend(dry(&[1, 2]));
+        kitchen.pour(1, 1);
+        kitchen.clean(1);
+
+        assert_eq!(kitchen.mixing_bowl(1), &[]);
+        assert_eq!(kitchen.baking_dish(1), dry(&[1, 2]));
+    }
+
+    #[test]
+    fn test_serve_empties_first_dishes() {
+        let mut kitchen = Kitchen::new();
+        kitchen.bowl_mut(1).extend(dry(&[1, 2]));
+        kitchen.bowl_mut(2).extend(dry(&[3, 4]));
+        kitchen.pour(1, 1);
+        kitchen.pour(2, 2);
+        kitchen.pour(2, 3);
+
+        let servings: Vec<_> = kitchen.servings(2).cloned().collect();
+        assert_eq!(kitchen.serve(2), servings);
+        assert_eq!(servings, dry(&[2, 1, 4, 3]));
+        assert_eq!(kitchen.baking_dish(1), &[]);
+        assert_eq!(kitchen.baking_dish(2), &[]);
+        assert_eq!(kitchen.baking_dish(3), dry(&[3, 4]));
+        assert_eq!(kitchen.mixing_bowl(1), dry(&[1, 2]));
+    }
+}
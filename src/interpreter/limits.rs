@@ -0,0 +1,145 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use chumsky::span::SimpleSpan;
+
+use super::{Frame, RuntimeError};
+
+/// Bounds on the resources a recipe may use, for running recipes that cannot be trusted.
+///
+/// Every limit is off by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Method steps executed, counting every pass through the end of a loop as a step.
+    pub max_steps: Option<u64>,
+    /// Ingredients held in all bowls and dishes together, including the copies handed to sous-chefs.
+    pub max_items: Option<usize>,
+    /// Sous-chefs working at the same time, each one called by the last.
+    pub max_depth: Option<usize>,
+    /// Bytes served.
+    pub max_output_bytes: Option<usize>,
+    /// How long a whole run may take.
+    pub timeout: Option<Duration>,
+}
+
+/// A limit from [`Limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    Items(usize),
+    Depth(usize),
+    OutputBytes(usize),
+    Timeout(Duration),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "Step limit of {max} exceeded"),
+            Limit::Items(max) => write!(f, "Ingredient limit of {max} exceeded"),
+            Limit::Depth(max) => write!(f, "Sous-chef limit of {max} exceeded"),
+            Limit::OutputBytes(max) => write!(f, "Output limit of {max} bytes exceeded"),
+            Limit::Timeout(max) => write!(f, "Time limit of {max:?} exceeded"),
+        }
+    }
+}
+
+/// Resources used so far by a run, checked against its [`Limits`].
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Usage {
+    steps: u64,
+    depth: usize,
+    output_bytes: usize,
+    /// Items in the kitchens of all chefs waiting on a sous-chef
+    items_in_callers: usize,
+    deadline: Option<Instant>,
+}
+
+impl Usage {
+    pub(crate) fn start(limits: &Limits) -> Self {
+        Self {
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            ..Self::default()
+        }
+    }
+
+    /// Starts counting again for `frames` that were already being prepared.
+    pub(crate) fn resume<N: Clone>(limits: &Limits, frames: &[Frame<N>]) -> Self {
+        let callers = &frames[..frames.len().saturating_sub(1)];
+        Self {
+            depth: callers.len(),
+            items_in_callers: callers.iter().map(|frame| frame.kitchen().items()).sum(),
+            ..Self::start(limits)
+        }
+    }
+
+    pub(crate) fn step(&mut self, limits: &Limits, span: &SimpleSpan) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if let Some(max) = limits.max_steps.filter(|max| self.steps > *max) {
+            return Err(exceeded(Limit::Steps(max), span));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, limits.timeout) {
+            if Instant::now() > deadline {
+                return Err(exceeded(Limit::Timeout(timeout), span));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_items(
+        &self,
+        limits: &Limits,
+        items: usize,
+        span: &SimpleSpan,
+    ) -> Result<(), RuntimeError> {
+        match limits.max_items {
+            Some(max) if self.items_in_callers + items > max => {
+                Err(exceeded(Limit::Items(max), span))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Called when a chef holding `items` hands over to a sous-chef.
+    pub(crate) fn enter(
+        &mut self,
+        limits: &Limits,
+        items: usize,
+        span: &SimpleSpan,
+    ) -> Result<(), RuntimeError> {
+        if let Some(max) = limits.max_depth.filter(|max| self.depth >= *max) {
+            return Err(exceeded(Limit::Depth(max), span));
+        }
+        // Checked here too, as a sous-chef that only calls for another never checks its own
+        self.check_items(limits, items, span)?;
+        self.depth += 1;
+        self.items_in_callers += items;
+        Ok(())
+    }
+
+    /// Called when the sous-chef is done, with what was passed to [`Usage::enter`].
+    pub(crate) fn leave(&mut self, items: usize) {
+        self.depth -= 1;
+        self.items_in_callers -= items;
+    }
+
+    pub(crate) fn output(
+        &mut self,
+        limits: &Limits,
+        bytes: usize,
+        span: &SimpleSpan,
+    ) -> Result<(), RuntimeError> {
+        let output_bytes = self.output_bytes + bytes;
+        if let Some(max) = limits.max_output_bytes.filter(|max| output_bytes > *max) {
+            return Err(exceeded(Limit::OutputBytes(max), span));
+        }
+        self.output_bytes = output_bytes;
+        Ok(())
+    }
+}
+
+fn exceeded(limit: Limit, span: &SimpleSpan) -> RuntimeError {
+    RuntimeError::LimitExceeded { limit, span: *span }
+}
@@ -0,0 +1,1663 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+};
+
+use chumsky::span::SimpleSpan;
+
+use crate::parser::{
+    ChefProgram, ChefRecipe, Ingredient, IngredientKind, Instruction, Spanned, VerbLoop,
+};
+
+mod bytecode;
+mod coverage;
+mod error;
+mod frame;
+mod input;
+mod io;
+mod journal;
+mod kitchen;
+mod limits;
+mod number;
+mod observer;
+mod output;
+mod profile;
+mod rng;
+mod snapshot;
+mod vm;
+
+pub use bytecode::{compile, Bowl, Bytecode, CompiledRecipe, Op, RecipeId, Slot};
+pub use coverage::{Coverage, CoverageError, CoveredRecipe, CoveredStep};
+pub use error::{RuntimeError, StackFrame, TracedError};
+pub use frame::{Frame, Position};
+pub use input::{EndOfInput, Input};
+pub use io::{ChefIo, MemoryIo, StdIo};
+pub use journal::{Change, Container, Entry, Journal};
+pub use kitchen::Kitchen;
+pub use limits::{Limit, Limits};
+pub use number::{ArithmeticError, Number, Operation, Overflow};
+pub use observer::{Observer, Trace};
+pub use output::{InvalidCodepoints, Serving};
+pub use profile::{Profile, ProfiledRecipe, StepProfile};
+pub use rng::{ChefRng, NoShuffle, SeededRng};
+pub use snapshot::{Snapshot, SnapshotError};
+
+use limits::Usage;
+
+/// Runs the main recipe using STDIN and STDOUT, returning the head chef's kitchen as it was left when the recipe ended.
+///
+/// Bowls are mixed the same way on every run with the same `seed`, or differently each run without one.
+pub fn run(program: &ChefProgram, seed: Option<u64>) -> Result<Kitchen, TracedError> {
+    let interpreter = Interpreter::new();
+    match seed {
+        Some(seed) => interpreter.with_seed(seed),
+        None => interpreter,
+    }
+    .run_program(program)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IngredientAmount<N = i64> {
+    amount: N,
+    kind: IngredientKind,
+}
+impl<N: Number> IngredientAmount<N> {
+    pub fn new(amount: N, kind: IngredientKind) -> IngredientAmount<N> {
+        Self { amount, kind }
+    }
+    pub fn set_amount(&mut self, amount: N) {
+        self.amount = amount;
+    }
+    pub fn amount(&self) -> &N {
+        &self.amount
+    }
+    pub fn kind(&self) -> IngredientKind {
+        self.kind
+    }
+}
+
+/// Whether the main recipe is still being prepared after an [`Interpreter::step`].
+#[derive(Debug)]
+pub enum Status<N = i64> {
+    Running,
+    /// The main recipe has ended, leaving the head chef's kitchen as it is.
+    Finished(Kitchen<N>),
+}
+
+/// What to do with the frame of the recipe that just took a step.
+enum Action<N> {
+    Next,
+    /// Wait for a sous-chef to prepare another recipe.
+    Call(Box<Frame<N>>),
+    /// The recipe has ended.
+    Return,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct EvalContext<N> {
+    kinds: HashMap<String, IngredientKind>,
+    values: HashMap<String, IngredientAmount<N>>,
+    /// Names in the order they are first declared, which dry ingredients are added up in.
+    declared: Vec<String>,
+    kitchen: Kitchen<N>,
+}
+impl<N: Number> EvalContext<N> {
+    /// Measures out the ingredients of a recipe about to be prepared in `kitchen`.
+    fn new(
+        recipe: &ChefRecipe<'_, Instruction, Ingredient>,
+        kitchen: Kitchen<N>,
+    ) -> Result<Self, RuntimeError> {
+        let mut values = HashMap::new();
+        let mut kinds = HashMap::new();
+        for Spanned(ingredient, span) in &recipe.ingredients {
+            let name = ingredient.name.to_lowercase();
+            kinds.insert(name.clone(), ingredient.kind);
+            let Some(initial_value) = ingredient.initial_value else {
+                // Silently ignore ingredients without initial values
+                // This is according to spec. Later, when trying to use the ingredient and it has no valuie,
+                // we will raise a runtime error
+                continue;
+            };
+            let Some(initial_value) = N::from_usize(initial_value) else {
+                return Err(RuntimeError::InitialValueTooLarge {
+                    value: initial_value,
+                    span: *span,
+                });
+            };
+            values.insert(name, IngredientAmount::new(initial_value, ingredient.kind));
+        }
+        Ok(Self {
+            values,
+            kinds,
+            declared: Self::declared(recipe),
+            kitchen,
+        })
+    }
+
+    /// Names of the ingredients of `recipe`, in the order they are first declared.
+    fn declared(recipe: &ChefRecipe<'_, Instruction, Ingredient>) -> Vec<String> {
+        let mut declared: Vec<String> = Vec::new();
+        for Spanned(ingredient, _) in &recipe.ingredients {
+            let name = ingredient.name.to_lowercase();
+            if !declared.contains(&name) {
+                declared.push(name);
+            }
+        }
+        declared
+    }
+
+    fn value(
+        &self,
+        ingredient_name: &str,
+        span: &SimpleSpan,
+    ) -> Result<&IngredientAmount<N>, RuntimeError> {
+        self.values
+            .get(&ingredient_name.to_lowercase())
+            .ok_or_else(|| self.missing(ingredient_name, span))
+    }
+}
+
+impl<N> EvalContext<N> {
+    /// The error for an ingredient without a value.
+    fn missing(&self, ingredient_name: &str, span: &SimpleSpan) -> RuntimeError {
+        let name = ingredient_name.to_string();
+        let span = *span;
+        if self.kinds.contains_key(&ingredient_name.to_lowercase()) {
+            RuntimeError::UninitializedIngredient { name, span }
+        } else {
+            RuntimeError::UndefinedIngredient { name, span }
+        }
+    }
+}
+
+/// Runs Chef programs, taking ingredients from the refrigerator and serving dishes through `I`.
+///
+/// Ingredients hold values of type `N`.
+pub struct Interpreter<I = StdIo, N = i64> {
+    io: I,
+    rng: Box<dyn ChefRng>,
+    overflow: Overflow,
+    limits: Limits,
+    serving: Serving,
+    input: Input,
+    /// Characters read but not taken yet, when taking characters
+    unread: VecDeque<char>,
+    /// Watching every step, in the order they were added
+    observers: Vec<Box<dyn Observer<N>>>,
+    journal: Option<Journal<N>>,
+    usage: Usage,
+    frames: Vec<Frame<N>>,
+    number: PhantomData<N>,
+}
+
+impl Interpreter<StdIo> {
+    pub fn new() -> Self {
+        Self::with_io(StdIo)
+    }
+}
+
+impl Default for Interpreter<StdIo> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: ChefIo> Interpreter<I> {
+    /// An interpreter that takes input from and serves output to `io`.
+    pub fn with_io(io: I) -> Self {
+        Self {
+            io,
+            rng: Box::new(SeededRng::from_entropy()),
+            overflow: Overflow::default(),
+            limits: Limits::default(),
+            serving: Serving::default(),
+            input: Input::default(),
+            unread: VecDeque::new(),
+            observers: Vec::new(),
+            journal: None,
+            usage: Usage::default(),
+            frames: Vec::new(),
+            number: PhantomData,
+        }
+    }
+}
+
+impl<I: ChefIo, N: Number> Interpreter<I, N> {
+    /// Uses `M` for ingredient values instead, dropping any [`Observer`] or [`Journal`] of the old
+    /// values.
+    pub fn with_number<M: Number>(self) -> Interpreter<I, M> {
+        Interpreter {
+            io: self.io,
+            rng: self.rng,
+            overflow: self.overflow,
+            limits: self.limits,
+            serving: self.serving,
+            input: self.input,
+            unread: self.unread,
+            observers: Vec::new(),
+            journal: None,
+            usage: self.usage,
+            frames: Vec::new(),
+            number: PhantomData,
+        }
+    }
+
+    /// Mixes bowls the same way on every run with the same `seed`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.with_rng(SeededRng::new(seed))
+    }
+
+    /// Uses `rng` to mix bowls, for example [`NoShuffle`] to leave them as they are.
+    pub fn with_rng(mut self, rng: impl ChefRng + 'static) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Sets what happens when arithmetic does not fit the number type. Defaults to [`Overflow::Checked`].
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Stops recipes that use more than `limits` allow. Recipes are not limited by default.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets how liquid ingredients are served. Invalid characters are an error by default.
+    pub fn with_serving(mut self, serving: Serving) -> Self {
+        self.serving = serving;
+        self
+    }
+
+    /// Sets how `Take` reads input. By default it takes a number per line and running out is an error.
+    pub fn with_input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Has `observer` watch every step too. Observers are called in the order they were added
+    /// before a step and when a sous-chef starts, and in the reverse order after a step and when
+    /// a recipe ends, so the last one added sees the step most closely.
+    pub fn with_observer(mut self, observer: impl Observer<N> + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Journals the changes made by the most recent steps, up to about `capacity` bytes of them,
+    /// so they can be undone with [`step_back`](Self::step_back).
+    pub fn with_journal(mut self, capacity: usize) -> Self {
+        self.journal = Some(Journal::new(capacity));
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Journal<N>> {
+        self.journal.as_ref()
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    /// Runs the main recipe, returning the head chef's kitchen as it was left when the recipe ended.
+    pub fn run_program(&mut self, program: &ChefProgram) -> Result<Kitchen<N>, TracedError> {
+        if let Err(error) = self.start(program) {
+            let main = StackFrame {
+                title: program.main.title.to_string(),
+                call_span: None,
+            };
+            return Err(TracedError {
+                error,
+                backtrace: vec![main],
+            });
+        }
+        self.run_to_end(program)
+    }
+
+    /// Carries on until the main recipe ends, after a [`start`](Self::start) or
+    /// [`resume`](Self::resume).
+    pub fn run_to_end(&mut self, program: &ChefProgram) -> Result<Kitchen<N>, TracedError> {
+        loop {
+            match self.step(program) {
+                Ok(Status::Running) => {}
+                Ok(Status::Finished(kitchen)) => return Ok(kitchen),
+                Err(error) => return Err(self.traced(error)),
+            }
+        }
+    }
+
+    /// Attaches the recipes being prepared to an error from [`step`](Self::step).
+    pub fn traced(&self, error: RuntimeError) -> TracedError {
+        let backtrace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| StackFrame {
+                title: frame.recipe_title().to_string(),
+                call_span: frame.return_span(),
+            })
+            .collect();
+        TracedError { error, backtrace }
+    }
+
+    /// Gets the head chef ready to prepare the main recipe, one [`step`](Self::step) at a time.
+    pub fn start(&mut self, program: &ChefProgram) -> Result<(), RuntimeError> {
+        self.usage = Usage::start(&self.limits);
+        self.frames.clear();
+        let ctx = EvalContext::new(&program.main, Kitchen::new())?;
+        self.frames
+            .push(Frame::new(None, program.main.title, None, ctx));
+        Ok(())
+    }
+
+    /// The recipes being prepared, the main recipe first and the innermost sous-chef last.
+    pub fn frames(&self) -> &[Frame<N>] {
+        &self.frames
+    }
+
+    /// Goes back to recipes saved from [`frames`](Self::frames) for the same program, to carry on
+    /// from there. Limits count from zero again.
+    pub fn restore(&mut self, frames: Vec<Frame<N>>) {
+        self.usage = Usage::resume(&self.limits, &frames);
+        self.frames = frames;
+    }
+
+    /// Saves the recipes being prepared, the state of the generator mixing bowls and any input
+    /// read but not taken yet, for the program with `source`.
+    pub fn snapshot(&self, source: &str) -> Snapshot<N> {
+        let unread = self.unread.iter().copied().collect();
+        Snapshot::new(source, self.rng.state(), unread, &self.frames)
+    }
+
+    /// Carries on from a [`snapshot`](Self::snapshot) of `program`, refusing one taken from other
+    /// `source`. Limits count from zero again.
+    ///
+    /// Bowls are mixed as they would have been without the break, unless the generator could not
+    /// be saved, in which case this interpreter's generator is used.
+    pub fn resume(
+        &mut self,
+        program: &ChefProgram,
+        source: &str,
+        snapshot: &Snapshot<N>,
+    ) -> Result<(), SnapshotError> {
+        let frames = snapshot.frames(program, source)?;
+        if let Some(state) = snapshot.rng() {
+            self.rng = Box::new(SeededRng::new(state));
+        }
+        self.unread = snapshot.unread().iter().copied().collect();
+        self.restore(frames);
+        Ok(())
+    }
+
+    /// Runs the next method step of the innermost recipe being prepared, or the `until` ending a loop.
+    ///
+    /// When a step fails, the frames are left as they were before it.
+    ///
+    /// # Panics
+    ///
+    /// If no recipe was [`start`](Self::start)ed, or the last one has finished.
+    pub fn step(&mut self, program: &ChefProgram) -> Result<Status<N>, RuntimeError> {
+        if let Some(journal) = &mut self.journal {
+            journal.begin(program, &self.frames, self.rng.state(), self.unread.len());
+        }
+        let status = self.step_journaled(program)?;
+        if let Some(journal) = &mut self.journal {
+            journal.commit();
+        }
+        Ok(status)
+    }
+
+    /// Undoes the last step [`journal`](Self::with_journal)ed, returning what it changed, or
+    /// `None` if there is none. A main recipe that ended is prepared again from before its last
+    /// step. Limits count from zero again.
+    pub fn step_back(&mut self) -> Option<Entry<N>> {
+        let entry = self.journal.as_mut()?.pop()?;
+        if let Some(state) = entry.undo(&mut self.frames, &mut self.unread) {
+            self.rng = Box::new(SeededRng::new(state));
+        }
+        self.usage = Usage::resume(&self.limits, &self.frames);
+        Some(entry)
+    }
+
+    fn step_journaled(&mut self, program: &ChefProgram) -> Result<Status<N>, RuntimeError> {
+        let mut frame = self.frames.pop().expect("No recipe is being prepared");
+        let position = frame.position(program);
+        for observer in &mut self.observers {
+            observer.before(&frame, &position);
+        }
+        let action = self.step_frame(program, position, &mut frame);
+        if action.is_ok() {
+            for observer in self.observers.iter_mut().rev() {
+                observer.after(&frame, &position);
+            }
+        }
+        match action {
+            Ok(Action::Next) => {
+                self.frames.push(frame);
+                Ok(Status::Running)
+            }
+            Ok(Action::Call(sous_chef)) => {
+                for observer in &mut self.observers {
+                    observer.enter(&sous_chef);
+                }
+                if let Some(journal) = &mut self.journal {
+                    journal.change(Change::Call {
+                        frame: self.frames.len() + 1,
+                        title: sous_chef.recipe_title().to_string(),
+                    });
+                }
+                self.frames.push(frame);
+                self.frames.push(*sous_chef);
+                Ok(Status::Running)
+            }
+            Ok(Action::Return) => {
+                for observer in self.observers.iter_mut().rev() {
+                    observer.leave(&frame);
+                }
+                Ok(self.finish_recipe(frame))
+            }
+            Err(e) => {
+                self.frames.push(frame);
+                Err(e)
+            }
+        }
+    }
+
+    fn step_frame(
+        &mut self,
+        program: &ChefProgram,
+        position: Position,
+        frame: &mut Frame<N>,
+    ) -> Result<Action<N>, RuntimeError> {
+        let instruction = match position {
+            Position::Instruction(instruction) => instruction,
+            Position::EndOfLoop(Spanned(_, span), verb_loop) => {
+                // Reaching the `until` statement is a step of its own
+                self.usage.step(&self.limits, span)?;
+                if let Some(until_ingredient) = verb_loop.until_ingredient {
+                    let one = N::from_usize(1).expect("Every number type has a one");
+                    let value = frame.ctx.value(until_ingredient, span)?;
+                    let decremented = value
+                        .amount()
+                        .apply(Operation::Subtract, &one, self.overflow)
+                        .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+                    let decremented = IngredientAmount::new(decremented, value.kind);
+                    let name = until_ingredient.to_lowercase();
+                    self.journal_write(&frame.ctx, &name, &decremented);
+                    frame.ctx.values.insert(name, decremented);
+                }
+                if frame
+                    .ctx
+                    .value(verb_loop.ingredient, span)?
+                    .amount()
+                    .is_zero()
+                {
+                    frame.exit_loop();
+                } else {
+                    frame.repeat_loop();
+                }
+                return Ok(Action::Next);
+            }
+            Position::EndOfRecipe(serves) => {
+                self.check_return(frame)?;
+                if let Some(Spanned(diners, span)) = serves {
+                    self.serve(&mut frame.ctx, *diners, span)?;
+                }
+                return Ok(Action::Return);
+            }
+        };
+
+        let Spanned(instruction, span) = instruction;
+        self.usage.step(&self.limits, span)?;
+        match instruction {
+            Instruction::VerbLoop(VerbLoop { ingredient, .. }) => {
+                let enter = !frame.ctx.value(ingredient, span)?.amount().is_zero();
+                frame.advance();
+                if enter {
+                    frame.enter_loop();
+                }
+            }
+            Instruction::SetAside => {
+                // Setting aside outside of a loop ends the recipe, like refrigerating it does
+                if !frame.exit_loop() {
+                    self.check_return(frame)?;
+                    frame.advance();
+                    return Ok(Action::Return);
+                }
+            }
+            Instruction::ServeWith(recipe_name) => {
+                let sous_chef = self.serve_with(program, &frame.ctx, recipe_name, span)?;
+                frame.advance();
+                return Ok(Action::Call(Box::new(sous_chef)));
+            }
+            Instruction::Take(ingredient_name) => {
+                let Some(value) = self.read_input(span)? else {
+                    self.check_return(frame)?;
+                    frame.advance();
+                    return Ok(Action::Return);
+                };
+                let name = ingredient_name.to_lowercase();
+                let Some(kind) = frame.ctx.kinds.get(&name) else {
+                    return Err(frame.ctx.missing(ingredient_name, span));
+                };
+                let taken = IngredientAmount::new(value, *kind);
+                self.journal_write(&frame.ctx, &name, &taken);
+                frame.ctx.values.insert(name, taken);
+                frame.advance();
+            }
+            Instruction::Refrigerate(hours) => {
+                self.check_return(frame)?;
+                if let Some(diners) = hours {
+                    self.serve(&mut frame.ctx, *diners, span)?;
+                }
+                frame.advance();
+                return Ok(Action::Return);
+            }
+            _ => {
+                self.eval_instruction(instruction, span, &mut frame.ctx)?;
+                frame.advance();
+                self.usage
+                    .check_items(&self.limits, frame.ctx.kitchen.items(), span)?;
+            }
+        }
+        Ok(Action::Next)
+    }
+
+    /// Checks that the chef that called for `frame` can hold its first mixing bowl, before a step
+    /// ends the recipe and changes anything.
+    fn check_return(&self, frame: &Frame<N>) -> Result<(), RuntimeError> {
+        let Some(span) = frame.return_span() else {
+            return Ok(());
+        };
+        // The caller's ingredients are counted until the sous-chef is done
+        let handed = frame.kitchen().mixing_bowl(1).len();
+        self.usage.check_items(&self.limits, handed, &span)
+    }
+
+    /// Hands the first mixing bowl of a finished recipe back to the chef that called for it.
+    fn finish_recipe(&mut self, frame: Frame<N>) -> Status<N> {
+        let index = self.frames.len();
+        let Some(caller) = self.frames.last_mut() else {
+            if let Some(journal) = &mut self.journal {
+                let ended = Box::new(frame.clone());
+                journal.change(Change::Return {
+                    frame: index,
+                    ended,
+                });
+            }
+            return Status::Finished(frame.into_kitchen());
+        };
+        self.usage.leave(caller.ctx.kitchen.items());
+        match &mut self.journal {
+            // The ended recipe is journaled as it was, so its first bowl is copied to the caller
+            Some(journal) => {
+                let handed = frame.kitchen().mixing_bowl(1).to_vec();
+                if !caller.ctx.kitchen.has_bowl(1) {
+                    journal.created(index - 1, Container::MixingBowl, 1);
+                }
+                caller
+                    .ctx
+                    .kitchen
+                    .bowl_mut(1)
+                    .extend(handed.iter().cloned());
+                if !handed.is_empty() {
+                    journal.change(Change::Push {
+                        frame: index - 1,
+                        container: Container::MixingBowl,
+                        number: 1,
+                        amounts: handed,
+                    });
+                }
+                let ended = Box::new(frame);
+                journal.change(Change::Return {
+                    frame: index,
+                    ended,
+                });
+            }
+            None => caller
+                .ctx
+                .kitchen
+                .bowl_mut(1)
+                .extend(frame.into_kitchen().into_first_bowl()),
+        }
+        Status::Running
+    }
+
+    /// Runs an instruction that does not change which instruction runs next.
+    fn eval_instruction(
+        &mut self,
+        instruction: &Instruction,
+        span: &SimpleSpan,
+        ctx: &mut EvalContext<N>,
+    ) -> Result<(), RuntimeError> {
+        match instruction {
+            Instruction::Put(ingredient_name, bowl) => {
+                modify_bowl(ctx, span, ingredient_name, *bowl, |bowl, value| {
+                    bowl.push(value.clone());
+                    Ok(())
+                })?;
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::Fold(ingredient_name, bowl) => {
+                let name = ingredient_name.to_lowercase();
+                let Some(kind) = ctx.kinds.get(&name).copied() else {
+                    return Err(ctx.missing(ingredient_name, span));
+                };
+                let Some(value_from_bowl) = ctx.kitchen.bowl_mut(*bowl).pop() else {
+                    return Err(RuntimeError::EmptyBowl {
+                        bowl: *bowl,
+                        span: *span,
+                    });
+                };
+                // Folding gives an ingredient a value even if it was declared without one
+                let kind = ctx.values.get(&name).map_or(kind, |value| value.kind);
+                let folded = IngredientAmount::new(value_from_bowl.amount.clone(), kind);
+                self.journal_write(ctx, &name, &folded);
+                if let Some(journal) = &mut self.journal {
+                    journal.change(Change::Pop {
+                        frame: self.frames.len(),
+                        container: Container::MixingBowl,
+                        number: *bowl,
+                        amounts: vec![value_from_bowl],
+                    });
+                }
+                ctx.values.insert(name, folded);
+            }
+            Instruction::Add(ingredient_name, bowl) => {
+                binary_op(
+                    ctx,
+                    span,
+                    ingredient_name,
+                    *bowl,
+                    Operation::Add,
+                    self.overflow,
+                )?;
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::Remove(ingredient_name, bowl) => {
+                binary_op(
+                    ctx,
+                    span,
+                    ingredient_name,
+                    *bowl,
+                    Operation::Subtract,
+                    self.overflow,
+                )?;
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::Combine(ingredient_name, bowl) => {
+                binary_op(
+                    ctx,
+                    span,
+                    ingredient_name,
+                    *bowl,
+                    Operation::Multiply,
+                    self.overflow,
+                )?;
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::Divide(ingredient_name, bowl) => {
+                binary_op(
+                    ctx,
+                    span,
+                    ingredient_name,
+                    *bowl,
+                    Operation::Divide,
+                    self.overflow,
+                )?;
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::AddDryIngredients(bowl) => {
+                let mut dry_ingredients = N::from_usize(0).expect("Every number type has a zero");
+                // In the order they are declared, so an overflow is the same from run to run
+                for value in ctx.declared.iter().filter_map(|name| ctx.values.get(name)) {
+                    if value.kind == IngredientKind::Dry {
+                        dry_ingredients = dry_ingredients
+                            .apply(Operation::Add, &value.amount, self.overflow)
+                            .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+                    }
+                }
+                ctx.kitchen
+                    .bowl_mut(*bowl)
+                    .push(IngredientAmount::new(dry_ingredients, IngredientKind::Dry));
+                self.journal_pushed(ctx, Container::MixingBowl, *bowl, 1);
+            }
+            Instruction::Liquefy(ingredient_name) => {
+                let mut liquid = ctx.value(ingredient_name, span)?.clone();
+                liquid.kind = IngredientKind::Wet;
+                let name = ingredient_name.to_lowercase();
+                self.journal_write(ctx, &name, &liquid);
+                ctx.values.insert(name, liquid);
+            }
+            Instruction::LiquefyContents(bowl) => {
+                let old = self.journaled(|| ctx.kitchen.mixing_bowl(*bowl).to_vec());
+                for ingredient in ctx.kitchen.bowl_mut(*bowl) {
+                    ingredient.kind = IngredientKind::Wet;
+                }
+                if let (Some(journal), Some(old)) = (&mut self.journal, old) {
+                    if !old.is_empty() {
+                        let frame = self.frames.len();
+                        let (container, number) = (Container::MixingBowl, *bowl);
+                        let new = ctx.kitchen.mixing_bowl(*bowl).to_vec();
+                        journal.change(Change::Pop {
+                            frame,
+                            container,
+                            number,
+                            amounts: old,
+                        });
+                        journal.change(Change::Push {
+                            frame,
+                            container,
+                            number,
+                            amounts: new,
+                        });
+                    }
+                }
+            }
+            Instruction::Stir(bowl, minutes) => {
+                let at = stir(ctx.kitchen.bowl_mut(*bowl), *bowl, *minutes, span)?;
+                self.journal_stirred(ctx, *bowl, at);
+            }
+            Instruction::StirIngredient(ingredient_name, bowl) => {
+                let value = ctx.value(ingredient_name, span)?.amount();
+                let Some(minutes) = value.to_usize() else {
+                    return Err(RuntimeError::InvalidMinutes {
+                        value: value.to_string(),
+                        span: *span,
+                    });
+                };
+                let at = stir(ctx.kitchen.bowl_mut(*bowl), *bowl, minutes, span)?;
+                self.journal_stirred(ctx, *bowl, at);
+            }
+            Instruction::Mix(bowl) => {
+                let old = self.journaled(|| ctx.kitchen.mixing_bowl(*bowl).to_vec());
+                rng::shuffle(self.rng.as_mut(), ctx.kitchen.bowl_mut(*bowl));
+                if let (Some(journal), Some(old)) = (&mut self.journal, old) {
+                    if !old.is_empty() {
+                        journal.change(Change::Reorder {
+                            frame: self.frames.len(),
+                            container: Container::MixingBowl,
+                            number: *bowl,
+                            new: ctx.kitchen.mixing_bowl(*bowl).to_vec(),
+                            old,
+                        });
+                    }
+                }
+            }
+            Instruction::Clean(bowl) => {
+                let cleaned = ctx.kitchen.clean(*bowl);
+                if let Some(journal) = &mut self.journal {
+                    if !cleaned.is_empty() {
+                        journal.change(Change::Pop {
+                            frame: self.frames.len(),
+                            container: Container::MixingBowl,
+                            number: *bowl,
+                            amounts: cleaned,
+                        });
+                    }
+                }
+            }
+            Instruction::Pour(bowl, dish) => {
+                let poured = ctx.kitchen.mixing_bowl(*bowl).len();
+                ctx.kitchen.pour(*bowl, *dish);
+                self.journal_pushed(ctx, Container::BakingDish, *dish, poured);
+            }
+            Instruction::Serves(diners) => {
+                self.serve(ctx, *diners, span)?;
+            }
+            Instruction::Take(_)
+            | Instruction::VerbLoop(_)
+            | Instruction::SetAside
+            | Instruction::ServeWith(_)
+            | Instruction::Refrigerate(_) => unreachable!("Handled by step_frame"),
+        };
+        Ok(())
+    }
+
+    /// Has a sous-chef prepare an auxiliary recipe.
+    ///
+    /// The sous-chef starts from copies of all our bowls and dishes and measures out the
+    /// ingredients of the auxiliary recipe for themselves, so nothing they do is visible to us,
+    /// except for their first mixing bowl which is emptied on top of ours when they are done.
+    /// Recipes may call themselves, each call getting a fresh sous-chef.
+    fn serve_with(
+        &mut self,
+        program: &ChefProgram,
+        ctx: &EvalContext<N>,
+        recipe_name: &str,
+        span: &SimpleSpan,
+    ) -> Result<Frame<N>, RuntimeError> {
+        let key = recipe_name.to_lowercase();
+        let Some(recipe) = program.auxilary.get(&key) else {
+            return Err(RuntimeError::UndefinedRecipe {
+                name: recipe_name.to_string(),
+                span: *span,
+            });
+        };
+        let sous_chef = EvalContext::new(recipe, ctx.kitchen.clone())?;
+        self.usage.enter(&self.limits, ctx.kitchen.items(), span)?;
+        Ok(Frame::new(Some(key), recipe.title, Some(*span), sous_chef))
+    }
+
+    /// Serves the first `diners` baking dishes, emptying each one from the top.
+    fn serve(
+        &mut self,
+        ctx: &mut EvalContext<N>,
+        diners: usize,
+        span: &SimpleSpan,
+    ) -> Result<(), RuntimeError> {
+        self.serve_ingredients(ctx.kitchen.servings(diners), span)?;
+        // Emptied only once served, so a step that fails to serve leaves the dishes as they were
+        if let Some(journal) = &mut self.journal {
+            for dish in 1..=diners {
+                let contents = ctx.kitchen.baking_dish(dish);
+                if !contents.is_empty() {
+                    journal.change(Change::Pop {
+                        frame: self.frames.len(),
+                        container: Container::BakingDish,
+                        number: dish,
+                        amounts: contents.to_vec(),
+                    });
+                }
+            }
+        }
+        ctx.kitchen.serve(diners);
+        Ok(())
+    }
+
+    /// Writes out ingredients from baking dishes, in the order they are served.
+    fn serve_ingredients<'a>(
+        &mut self,
+        served: impl IntoIterator<Item = &'a IngredientAmount<N>>,
+        span: &SimpleSpan,
+    ) -> Result<(), RuntimeError>
+    where
+        N: 'a,
+    {
+        let mut output = String::new();
+        for ingredient in served {
+            match ingredient.kind {
+                IngredientKind::Dry => self.serving.dry(&mut output, &ingredient.amount),
+                IngredientKind::Wet => {
+                    if !self.serving.liquid(&mut output, &ingredient.amount) {
+                        return Err(RuntimeError::InvalidCodepoint {
+                            value: ingredient.amount.to_string(),
+                            span: *span,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.usage.output(&self.limits, output.len(), span)?;
+        self.io
+            .write(&output)
+            .map_err(|e| RuntimeError::WriteOutput {
+                reason: e.to_string(),
+                span: *span,
+            })
+    }
+
+    /// Takes the next ingredient from the refrigerator, or `None` to end the recipe.
+    fn read_input(&mut self, span: &SimpleSpan) -> Result<Option<N>, RuntimeError> {
+        let value = match self.input.chars {
+            true => self
+                .read_char(span)?
+                .map(|c| N::from_usize(c as usize).expect("Every number type holds a character")),
+            false => self.read_number(span)?,
+        };
+        match (value, self.input.end_of_input) {
+            (Some(value), _) => Ok(Some(value)),
+            (None, EndOfInput::Error) => Err(RuntimeError::EndOfInput { span: *span }),
+            (None, EndOfInput::Zero) => Ok(Some(
+                N::from_usize(0).expect("Every number type has a zero"),
+            )),
+            (None, EndOfInput::Stop) => Ok(None),
+        }
+    }
+
+    fn read_number(&mut self, span: &SimpleSpan) -> Result<Option<N>, RuntimeError> {
+        while let Some(line) = self.read_line(span)? {
+            match N::parse(line.trim()) {
+                Some(num) => return Ok(Some(num)),
+                None => self.io.reject_input(&line, "not a number"),
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_char(&mut self, span: &SimpleSpan) -> Result<Option<char>, RuntimeError> {
+        while self.unread.is_empty() {
+            let Some(line) = self.read_line(span)? else {
+                return Ok(None);
+            };
+            self.unread.extend(line.chars());
+        }
+        let taken = self.unread.pop_front();
+        if let (Some(journal), Some(taken)) = (&mut self.journal, taken) {
+            journal.take(taken);
+        }
+        Ok(taken)
+    }
+
+    fn read_line(&mut self, span: &SimpleSpan) -> Result<Option<String>, RuntimeError> {
+        self.io.read_line().map_err(|e| RuntimeError::ReadInput {
+            reason: e.to_string(),
+            span: *span,
+        })
+    }
+
+    /// What `save` returns, if steps are journaled.
+    fn journaled<T>(&self, save: impl FnOnce() -> T) -> Option<T> {
+        self.journal.is_some().then(save)
+    }
+
+    /// Journals that the ingredient `name`, in lowercase, is about to be given the value `new`.
+    ///
+    /// Changes are made by the recipe taking a step, whose frame is off the stack while it does,
+    /// so it is the one after the frames.
+    fn journal_write(&mut self, ctx: &EvalContext<N>, name: &str, new: &IngredientAmount<N>) {
+        if let Some(journal) = &mut self.journal {
+            journal.change(Change::Ingredient {
+                frame: self.frames.len(),
+                name: name.to_string(),
+                old: ctx.values.get(name).cloned(),
+                new: new.clone(),
+            });
+        }
+    }
+
+    /// Journals the top `count` ingredients of a bowl or dish as just put there.
+    fn journal_pushed(
+        &mut self,
+        ctx: &EvalContext<N>,
+        container: Container,
+        number: usize,
+        count: usize,
+    ) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        let contents = match container {
+            Container::MixingBowl => ctx.kitchen.mixing_bowl(number),
+            Container::BakingDish => ctx.kitchen.baking_dish(number),
+        };
+        if count > 0 {
+            journal.change(Change::Push {
+                frame: self.frames.len(),
+                container,
+                number,
+                amounts: contents[contents.len() - count..].to_vec(),
+            });
+        }
+    }
+
+    /// Journals stirring the top ingredient of a bowl down to `at`, which rearranged the
+    /// ingredients from there up.
+    fn journal_stirred(&mut self, ctx: &EvalContext<N>, bowl: usize, at: usize) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        let new = ctx.kitchen.mixing_bowl(bowl)[at..].to_vec();
+        if new.len() > 1 {
+            let mut old = new.clone();
+            old.rotate_left(1);
+            journal.change(Change::Reorder {
+                frame: self.frames.len(),
+                container: Container::MixingBowl,
+                number: bowl,
+                old,
+                new,
+            });
+        }
+    }
+}
+
+fn binary_op<N: Number>(
+    ctx: &mut EvalContext<N>,
+    span: &SimpleSpan,
+    ingredient_name: &str,
+    bowl: usize,
+    op: Operation,
+    overflow: Overflow,
+) -> Result<(), RuntimeError> {
+    modify_bowl(ctx, span, ingredient_name, bowl, |contents, value| {
+        let Some(top) = contents.last() else {
+            return Err(RuntimeError::EmptyBowl { bowl, span: *span });
+        };
+        let amount = top
+            .amount
+            .apply(op, &value.amount, overflow)
+            .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+        contents.push(IngredientAmount::new(amount, value.kind));
+        Ok(())
+    })
+}
+
+fn modify_bowl<N: Number, F>(
+    ctx: &mut EvalContext<N>,
+    span: &SimpleSpan,
+    ingredient_name: &str,
+    bowl: usize,
+    op: F,
+) -> Result<(), RuntimeError>
+where
+    F: Fn(&mut Vec<IngredientAmount<N>>, &mut IngredientAmount<N>) -> Result<(), RuntimeError>,
+{
+    let missing = ctx.missing(ingredient_name, span);
+    let Some(ingredient_value) = ctx.values.get_mut(&ingredient_name.to_lowercase()) else {
+        return Err(missing);
+    };
+
+    let bowl = ctx.kitchen.bowl_mut(bowl);
+    op(bowl, ingredient_value)
+}
+
+/// Stirs the `contents` of the mixing bowl with the ordinal `bowl`, returning where the top
+/// ingredient went.
+fn stir<N>(
+    contents: &mut Vec<IngredientAmount<N>>,
+    bowl: usize,
+    minutes: usize,
+    span: &SimpleSpan,
+) -> Result<usize, RuntimeError> {
+    // This "rolls" the top number ingredients in the nth mixing bowl,
+    // such that the top ingredient goes down that number of ingredients
+    // and all ingredients above it rise one place.
+    // If there are not that many ingredients in the bowl,
+    // the top ingredient goes to tbe bottom of the bowl and
+    // all the others rise one place.
+    let Some(top) = contents.pop() else {
+        return Err(RuntimeError::EmptyBowl { bowl, span: *span });
+    };
+    let len = contents.len();
+    let new_position = len.saturating_sub(minutes);
+    contents.insert(new_position, top);
+    Ok(new_position)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_stir_contents() {
+        fn apply_stir(ingredients: &[i64], minutes: usize) -> Vec<i64> {
+            let mut ctx = EvalContext {
+                values: HashMap::new(),
+                kinds: HashMap::new(),
+                declared: Vec::new(),
+                kitchen: Kitchen::new(),
+            };
+            let bowl_index = 0;
+            let bowl = ctx.kitchen.bowl_mut(bowl_index);
+            for ingredient in ingredients {
+                bowl.push(IngredientAmount::new(*ingredient, IngredientKind::Dry));
+            }
+            Interpreter::with_io(MemoryIo::default())
+                .eval_instruction(
+                    &Instruction::Stir(bowl_index, minutes),
+                    &SimpleSpan::new(0, 0),
+                    &mut ctx,
+                )
+                .unwrap();
+
+            ctx.kitchen
+                .mixing_bowl(bowl_index)
+                .iter()
+                .map(|v| v.amount)
+                .collect()
+        }
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 0), vec![1, 2, 3, 4, 5]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 1), vec![1, 2, 3, 5, 4]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 2), vec![1, 2, 5, 3, 4]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 3), vec![1, 5, 2, 3, 4]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 4), vec![5, 1, 2, 3, 4]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 5), vec![5, 1, 2, 3, 4]);
+        assert_eq!(apply_stir(&[1, 2, 3, 4, 5], 6), vec![5, 1, 2, 3, 4]);
+    }
+
+    fn first_bowl(source: &str) -> Vec<i64> {
+        let program = crate::parser::parse_recipe(source);
+        Interpreter::with_io(MemoryIo::default())
+            .run_program(&program)
+            .unwrap()
+            .mixing_bowl(1)
+            .iter()
+            .map(|v| v.amount)
+            .collect()
+    }
+
+    #[test]
+    fn test_verb_loop() {
+        let source = r#"
+Countdown.
+
+Ingredients.
+3 eggs
+
+Method.
+Crack the eggs. Put eggs into mixing bowl. Whisk the eggs until cracked.
+"#
+        .trim();
+        assert_eq!(first_bowl(source), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_set_aside() {
+        let source = r#"
+Countdown.
+
+Ingredients.
+3 eggs
+
+Method.
+Crack the eggs. Put eggs into mixing bowl. Set aside. Whisk the eggs until cracked.
+"#
+        .trim();
+        assert_eq!(first_bowl(source), vec![3]);
+    }
+
+    #[test]
+    fn test_serve_with() {
+        let source = r#"
+Pancakes.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Serve with syrup. Put egg into mixing bowl.
+
+Syrup.
+
+Ingredients.
+5 g sugar
+
+Method.
+Put sugar into mixing bowl. Put sugar into the 2nd mixing bowl.
+"#
+        .trim();
+        assert_eq!(first_bowl(source), vec![1, 1, 5, 1]);
+    }
+
+    #[test]
+    fn test_serve_with_recursive_recipe() {
+        let source = r#"
+Countdown.
+
+Ingredients.
+3 eggs
+
+Method.
+Put eggs into the 2nd mixing bowl. Serve with egg timer. Put eggs into mixing bowl.
+
+Egg Timer.
+
+Ingredients.
+eggs
+1 g salt
+
+Method.
+Clean mixing bowl. Fold eggs into the 2nd mixing bowl. Put eggs into mixing bowl. Boil the eggs. Put eggs into the 2nd mixing bowl. Remove salt from the 2nd mixing bowl. Serve with egg timer. Set aside. Cool the eggs until boiled.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let kitchen = Interpreter::with_io(MemoryIo::default())
+            .run_program(&program)
+            .unwrap();
+        let amounts =
+            |contents: &[IngredientAmount]| contents.iter().map(|v| v.amount).collect::<Vec<_>>();
+
+        // Each sous-chef hands back their countdown, and the head chef still has 3 eggs
+        assert_eq!(amounts(kitchen.mixing_bowl(1)), vec![3, 2, 1, 0, 3]);
+        // Sous-chefs only ever touched copies of the 2nd mixing bowl
+        assert_eq!(amounts(kitchen.mixing_bowl(2)), vec![3]);
+    }
+
+    #[test]
+    fn test_arithmetic_errors_are_spanned() {
+        let source = r#"
+Flat Bread.
+
+Ingredients.
+1 egg
+0 g yeast
+
+Method.
+Put egg into mixing bowl. Divide yeast into mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let error = Interpreter::with_io(MemoryIo::default())
+            .run_program(&program)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Division by zero");
+        assert_eq!(
+            &source[error.span().into_range()],
+            "Divide yeast into mixing bowl"
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let source = r#"
+Layer Cake.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Serve with sponge.
+
+Sponge.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with icing.
+
+Icing.
+
+Ingredients.
+1 cup sugar
+
+Method.
+Fold sugar into the 2nd mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let error = Interpreter::with_io(MemoryIo::default())
+            .run_program(&program)
+            .unwrap_err();
+        assert!(matches!(
+            error.error,
+            RuntimeError::EmptyBowl { bowl: 2, .. }
+        ));
+        let backtrace: Vec<_> = error
+            .backtrace
+            .iter()
+            .map(|frame| {
+                let call = frame.call_span.map(|span| &source[span.into_range()]);
+                (frame.title.as_str(), call)
+            })
+            .collect();
+        assert_eq!(
+            backtrace,
+            vec![
+                ("Icing", Some("Serve with icing")),
+                ("Sponge", Some("Serve with sponge")),
+                ("Layer Cake", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        let source = r#"
+Rising Dough.
+
+Ingredients.
+9223372036854775807 g flour
+
+Method.
+Put flour into mixing bowl. Add flour to mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let run = |overflow| {
+            Interpreter::with_io(MemoryIo::default())
+                .with_overflow(overflow)
+                .run_program(&program)
+                .map(|kitchen| kitchen.mixing_bowl(1).last().unwrap().amount)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            run(Overflow::Checked),
+            Err("Arithmetic overflow".to_string())
+        );
+        assert_eq!(run(Overflow::Wrapping), Ok(-2));
+        assert_eq!(run(Overflow::Saturating), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn test_dry_ingredients_in_declared_order() {
+        let source = r#"
+Balanced Dough.
+
+Ingredients.
+9223372036854775807 g flour
+g sugar
+1 g salt
+
+Method.
+Take sugar from refrigerator. Add dry ingredients to mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        // Flour and salt alone would overflow, so they must not be added up before sugar
+        for _ in 0..16 {
+            let kitchen = Interpreter::with_io(MemoryIo::new("-1\n"))
+                .run_program(&program)
+                .unwrap();
+            assert_eq!(kitchen.mixing_bowl(1)[0].amount, i64::MAX);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let source = r#"
+Kitchen Sink.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with bottomless pot.
+
+Bottomless Pot.
+
+Ingredients.
+1 egg
+
+Method.
+Beat the egg. Put egg into mixing bowl. Serve with bottomless pot. Whisk until beaten.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let run = |limits| {
+            let error = Interpreter::with_io(MemoryIo::default())
+                .with_limits(limits)
+                .run_program(&program)
+                .unwrap_err();
+            (error.to_string(), &source[error.span().into_range()])
+        };
+
+        let limits = Limits {
+            max_steps: Some(9),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run(limits),
+            (
+                "Step limit of 9 exceeded".to_string(),
+                "Serve with bottomless pot"
+            )
+        );
+
+        let limits = Limits {
+            max_items: Some(10),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run(limits),
+            (
+                "Ingredient limit of 10 exceeded".to_string(),
+                "Put egg into mixing bowl"
+            )
+        );
+
+        let limits = Limits {
+            max_depth: Some(3),
+            ..Limits::default()
+        };
+        assert_eq!(
+            run(limits),
+            (
+                "Sous-chef limit of 3 exceeded".to_string(),
+                "Serve with bottomless pot"
+            )
+        );
+    }
+
+    #[test]
+    fn test_item_limit_on_call() {
+        let source = r#"
+Copied Eggs.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Put egg into mixing bowl. Put egg into mixing bowl. Serve with echo.
+
+Echo.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with echo.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let error = Interpreter::with_io(MemoryIo::default())
+            .with_limits(Limits {
+                max_items: Some(10),
+                ..Limits::default()
+            })
+            .run_program(&program)
+            .unwrap_err();
+        // Every sous-chef holds a copy of the 3 eggs, and only calls for another one
+        assert_eq!(error.to_string(), "Ingredient limit of 10 exceeded");
+        assert_eq!(error.backtrace.len(), 4);
+    }
+
+    #[test]
+    fn test_failed_return_changes_nothing() {
+        let source = r#"
+Leftovers.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Put egg into mixing bowl. Pour contents of the mixing bowl into the baking dish. Serve with fridge.
+
+Fridge.
+
+Ingredients.
+1 egg
+
+Method.
+Refrigerate for 2 hours.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let mut interpreter = Interpreter::with_io(MemoryIo::default()).with_limits(Limits {
+            max_items: Some(5),
+            ..Limits::default()
+        });
+        interpreter.start(&program).unwrap();
+        for _ in 0..4 {
+            interpreter.step(&program).unwrap();
+        }
+        let before = interpreter.frames().to_vec();
+
+        // Handing back the first bowl would leave the head chef with 6 eggs
+        for _ in 0..2 {
+            let error = interpreter.step(&program).unwrap_err();
+            assert_eq!(error.to_string(), "Ingredient limit of 5 exceeded");
+            assert_eq!(&source[error.span().into_range()], "Serve with fridge");
+            assert_eq!(interpreter.frames().len(), 2);
+            for (frame, before) in interpreter.frames().iter().zip(&before) {
+                assert_eq!(frame.pc(), before.pc());
+                assert_eq!(frame.kitchen(), before.kitchen());
+            }
+            assert_eq!(interpreter.io().output(), "");
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let source = r#"
+Slow Cooker.
+
+Ingredients.
+1 egg
+
+Method.
+Simmer the egg. Whisk until simmered.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let error = Interpreter::with_io(MemoryIo::default())
+            .with_limits(Limits {
+                timeout: Some(Duration::from_millis(10)),
+                ..Limits::default()
+            })
+            .run_program(&program)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Time limit of 10ms exceeded");
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let source = r#"
+Chatty Eggs.
+
+Ingredients.
+100 eggs
+
+Method.
+Put eggs into mixing bowl. Put eggs into mixing bowl. Pour contents of the mixing bowl into the baking dish.
+
+Serves 1.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let mut interpreter = Interpreter::with_io(MemoryIo::default()).with_limits(Limits {
+            max_output_bytes: Some(4),
+            ..Limits::default()
+        });
+        let error = interpreter.run_program(&program).unwrap_err();
+        assert_eq!(error.to_string(), "Output limit of 4 bytes exceeded");
+        assert_eq!(source[error.span().into_range()].trim(), "Serves 1.");
+        assert_eq!(interpreter.io().output(), "");
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        // Each sous-chef counts down by one and calls the next, far deeper than the Rust stack would allow
+        let source = r#"
+Deep Pot.
+
+Ingredients.
+100000 eggs
+
+Method.
+Put eggs into mixing bowl. Serve with deeper pot.
+
+Deeper Pot.
+
+Ingredients.
+eggs
+1 g salt
+
+Method.
+Fold eggs into mixing bowl. Put eggs into mixing bowl. Remove salt from mixing bowl. Fold eggs into mixing bowl. Clean mixing bowl. Boil the eggs. Put eggs into mixing bowl. Serve with deeper pot. Clean mixing bowl. Set aside. Cool the eggs until boiled.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let mut interpreter = Interpreter::with_io(MemoryIo::default());
+        interpreter.start(&program).unwrap();
+        let mut depth = 0;
+        let kitchen = loop {
+            depth = depth.max(interpreter.frames().len());
+            if let Status::Finished(kitchen) = interpreter.step(&program).unwrap() {
+                break kitchen;
+            }
+        };
+
+        assert_eq!(depth, 100001);
+        assert_eq!(
+            kitchen.mixing_bowl(1),
+            &[IngredientAmount::new(100000, IngredientKind::Dry)]
+        );
+    }
+
+    #[test]
+    fn test_take_modes() {
+        let source = r#"
+Alphabet Soup.
+
+Ingredients.
+letter
+
+Method.
+Take letter from refrigerator. Put letter into mixing bowl. Take letter from refrigerator. Put letter into mixing bowl. Take letter from refrigerator. Put letter into mixing bowl. Take letter from refrigerator. Put letter into mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let run = |input: &str, config| {
+            Interpreter::with_io(MemoryIo::new(input))
+                .with_input(config)
+                .run_program(&program)
+                .map(|kitchen| {
+                    let bowl = kitchen.mixing_bowl(1).iter();
+                    bowl.map(|v| v.amount).collect::<Vec<_>>()
+                })
+                .map_err(|e| e.to_string())
+        };
+
+        let numbers = Input::default();
+        assert_eq!(run(" -4 \n+3\r\nx\n2\n1\n", numbers), Ok(vec![-4, 3, 2, 1]));
+        assert_eq!(run("1\n", numbers), Err("Ran out of input".to_string()));
+        let zero = Input {
+            end_of_input: EndOfInput::Zero,
+            ..numbers
+        };
+        assert_eq!(run("1\n", zero), Ok(vec![1, 0, 0, 0]));
+        let stop = Input {
+            end_of_input: EndOfInput::Stop,
+            ..numbers
+        };
+        assert_eq!(run("1\n2", stop), Ok(vec![1, 2]));
+
+        let chars = Input {
+            chars: true,
+            end_of_input: EndOfInput::Zero,
+        };
+        assert_eq!(run("é\n", chars), Ok(vec![0xE9, 10, 0, 0]));
+        assert_eq!(run("a\n\nb", chars), Ok(vec![97, 10, 10, 98]));
+    }
+
+    #[test]
+    fn test_runtime_errors_are_typed() {
+        let run = |method: &str| {
+            let source =
+                format!("Empty Plate.\n\nIngredients.\n1114112 eggs\nflour\n\nMethod.\n{method}\n");
+            let program = crate::parser::parse_recipe(&source);
+            Interpreter::with_io(MemoryIo::default())
+                .run_program(&program)
+                .map(drop)
+                .unwrap_err()
+                .error
+        };
+
+        let error = run("Fold eggs into the 2nd mixing bowl.");
+        assert!(matches!(error, RuntimeError::EmptyBowl { bowl: 2, .. }));
+        assert_eq!(error.to_string(), "Bowl is empty");
+        assert_eq!(error.code(), "R0001");
+
+        let error = run("Put flour into mixing bowl.");
+        assert!(
+            matches!(error, RuntimeError::UninitializedIngredient { ref name, .. } if name == "flour")
+        );
+        assert_eq!(error.to_string(), "Ingredient `flour` has no value");
+
+        let error = run("Take sugar from refrigerator.");
+        assert!(matches!(error, RuntimeError::EndOfInput { .. }));
+
+        let error = run("Put eggs into mixing bowl. Liquefy contents of the mixing bowl. Pour contents of the mixing bowl into the baking dish. Refrigerate for 1 hours.");
+        assert!(matches!(error, RuntimeError::InvalidCodepoint { .. }));
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt::{Debug, Display};
+
+/// The arithmetic method steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// What happens when a fixed size number does not fit the result of an operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// The recipe fails with an error.
+    #[default]
+    Checked,
+    /// The result wraps around at the boundary of the type.
+    Wrapping,
+    /// The result is clamped to the smallest or largest value of the type.
+    Saturating,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticError {
+    Overflow,
+    Underflow,
+    DivisionByZero,
+}
+
+impl Display for ArithmeticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArithmeticError::Overflow => write!(f, "Arithmetic overflow"),
+            ArithmeticError::Underflow => write!(f, "Arithmetic underflow"),
+            ArithmeticError::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+/// The value of an ingredient.
+///
+/// Implemented for `i64`, which is the default, and for `num_bigint::BigInt` with the `bigint` feature.
+pub trait Number: Clone + Debug + Display + PartialEq + Eq {
+    fn from_usize(value: usize) -> Option<Self>;
+
+    /// Parses a number taken from the refrigerator.
+    fn parse(input: &str) -> Option<Self>;
+
+    fn to_usize(&self) -> Option<usize>;
+
+    fn to_u32(&self) -> Option<u32>;
+
+    fn is_zero(&self) -> bool;
+
+    /// Computes `self op rhs`, handling results that do not fit according to `overflow`.
+    fn apply(&self, op: Operation, rhs: &Self, overflow: Overflow)
+        -> Result<Self, ArithmeticError>;
+}
+
+impl Number for i64 {
+    fn from_usize(value: usize) -> Option<Self> {
+        value.try_into().ok()
+    }
+
+    fn parse(input: &str) -> Option<Self> {
+        input.parse().ok()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        (*self).try_into().ok()
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        (*self).try_into().ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn apply(
+        &self,
+        op: Operation,
+        rhs: &Self,
+        overflow: Overflow,
+    ) -> Result<Self, ArithmeticError> {
+        let (a, b) = (*self, *rhs);
+        if op == Operation::Divide && b == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        let checked = match op {
+            Operation::Add => a.checked_add(b),
+            Operation::Subtract => a.checked_sub(b),
+            Operation::Multiply => a.checked_mul(b),
+            Operation::Divide => a.checked_div(b),
+        };
+        if let Some(result) = checked {
+            return Ok(result);
+        }
+
+        // The exact result is out of range, so it is negative exactly when it is too small
+        let too_small = match op {
+            Operation::Add => b < 0,
+            Operation::Subtract => b > 0,
+            Operation::Multiply | Operation::Divide => (a < 0) != (b < 0),
+        };
+        match overflow {
+            Overflow::Checked if too_small => Err(ArithmeticError::Underflow),
+            Overflow::Checked => Err(ArithmeticError::Overflow),
+            Overflow::Wrapping => Ok(match op {
+                Operation::Add => a.wrapping_add(b),
+                Operation::Subtract => a.wrapping_sub(b),
+                Operation::Multiply => a.wrapping_mul(b),
+                Operation::Divide => a.wrapping_div(b),
+            }),
+            Overflow::Saturating if too_small => Ok(i64::MIN),
+            Overflow::Saturating => Ok(i64::MAX),
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Number for num_bigint::BigInt {
+    fn from_usize(value: usize) -> Option<Self> {
+        Some(value.into())
+    }
+
+    fn parse(input: &str) -> Option<Self> {
+        input.parse().ok()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        self.try_into().ok()
+    }
+
+    fn to_u32(&self) -> Option<u32> {
+        self.try_into().ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.sign() == num_bigint::Sign::NoSign
+    }
+
+    /// Big numbers never overflow, so `overflow` is ignored.
+    fn apply(
+        &self,
+        op: Operation,
+        rhs: &Self,
+        _overflow: Overflow,
+    ) -> Result<Self, ArithmeticError> {
+        Ok(match op {
+            Operation::Add => self + rhs,
+            Operation::Subtract => self - rhs,
+            Operation::Multiply => self * rhs,
+            Operation::Divide if rhs.is_zero() => return Err(ArithmeticError::DivisionByZero),
+            Operation::Divide => self / rhs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i64_overflow() {
+        use Operation::*;
+
+        let apply = |a: i64, op, b: i64, overflow| a.apply(op, &b, overflow);
+        assert_eq!(apply(2, Subtract, 3, Overflow::Checked), Ok(-1));
+        assert_eq!(
+            apply(i64::MAX, Add, 1, Overflow::Checked),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            apply(i64::MIN, Subtract, 1, Overflow::Checked),
+            Err(ArithmeticError::Underflow)
+        );
+        assert_eq!(
+            apply(i64::MIN, Multiply, 2, Overflow::Checked),
+            Err(ArithmeticError::Underflow)
+        );
+        assert_eq!(
+            apply(i64::MIN, Divide, -1, Overflow::Checked),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            apply(1, Divide, 0, Overflow::Saturating),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(apply(i64::MAX, Add, 1, Overflow::Wrapping), Ok(i64::MIN));
+        assert_eq!(apply(i64::MIN, Add, -1, Overflow::Saturating), Ok(i64::MIN));
+        assert_eq!(
+            apply(i64::MAX, Multiply, 3, Overflow::Saturating),
+            Ok(i64::MAX)
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint_does_not_overflow() {
+        use num_bigint::BigInt;
+
+        let max = BigInt::from(i64::MAX);
+        let sum = max.apply(Operation::Add, &max, Overflow::Checked).unwrap();
+        assert_eq!(sum.to_string(), "18446744073709551614");
+        assert_eq!(
+            sum.apply(Operation::Divide, &BigInt::from(0), Overflow::Checked),
+            Err(ArithmeticError::DivisionByZero)
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use std::{cell::RefCell, fmt::Write as _, io::Write, rc::Rc};
+
+use chumsky::span::SimpleSpan;
+
+use super::{journal, Container, Frame, IngredientAmount, Number, Position};
+use crate::parser::{IngredientKind, Instruction, Spanned};
+
+/// Watches every step an [`Interpreter`](super::Interpreter) takes.
+pub trait Observer<N> {
+    /// Called before `frame` takes the step at `position`.
+    fn before(&mut self, _frame: &Frame<N>, _position: &Position) {}
+
+    /// Called after the step succeeded, with the frame that took it. A sous-chef called for by
+    /// the step has not started yet, and a recipe the step ended has not handed back its first
+    /// mixing bowl yet.
+    fn after(&mut self, _frame: &Frame<N>, _position: &Position) {}
+
+    /// Called when a sous-chef starts preparing `frame`, after the step that called for them.
+    fn enter(&mut self, _frame: &Frame<N>) {}
+
+    /// Called when the recipe of `frame` has ended, after its last step.
+    fn leave(&mut self, _frame: &Frame<N>) {}
+}
+
+/// Shares an observer, so it can still be looked at once the interpreter is done with it.
+impl<N, O: Observer<N>> Observer<N> for Rc<RefCell<O>> {
+    fn before(&mut self, frame: &Frame<N>, position: &Position) {
+        self.borrow_mut().before(frame, position);
+    }
+
+    fn after(&mut self, frame: &Frame<N>, position: &Position) {
+        self.borrow_mut().after(frame, position);
+    }
+
+    fn enter(&mut self, frame: &Frame<N>) {
+        self.borrow_mut().enter(frame);
+    }
+
+    fn leave(&mut self, frame: &Frame<N>) {
+        self.borrow_mut().leave(frame);
+    }
+}
+
+/// Ingredients by lowercase name.
+type Values<N> = Vec<(String, IngredientAmount<N>)>;
+
+/// Contents of the bowls and dishes a step changes, by ordinal.
+type Touched<N> = Vec<(Container, usize, Vec<IngredientAmount<N>>)>;
+
+/// Contents of a bowl or dish before and after a step, by ordinal.
+type Change<'c, N> = (
+    Container,
+    usize,
+    &'c [IngredientAmount<N>],
+    &'c [IngredientAmount<N>],
+);
+
+/// Writes a line of JSON for every step, with what it read from and changed in the kitchen.
+///
+/// Each line holds the recipe, the span and line of the step, its kind, the values of the
+/// ingredients it read and wrote, and how bowls and dishes changed. A changed bowl is given as the
+/// position from the bottom where it starts to differ, the ingredients removed above it and the
+/// ones added. Dry values are numbers, liquid ones are `{"wet": value}`.
+///
+/// A `Serve with` step is written once the sous-chef is done, with the first mixing bowl they
+/// handed back, so it follows the steps of the recipe it called for.
+pub struct Trace<W, N> {
+    out: W,
+    /// Offset of the start of each line of the source
+    lines: Vec<usize>,
+    before: Option<(Values<N>, Touched<N>)>,
+    /// The line of each `Serve with` step waiting for its sous-chef, with the first mixing bowl
+    /// of the chef that took it
+    serving: Vec<(String, Vec<IngredientAmount<N>>)>,
+    failed: bool,
+}
+
+impl<W: Write, N: Number> Trace<W, N> {
+    /// Traces a program parsed from `source` to `out`.
+    pub fn new(out: W, source: &str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            out,
+            lines,
+            before: None,
+            serving: Vec::new(),
+            failed: false,
+        }
+    }
+
+    fn line(&self, span: SimpleSpan) -> usize {
+        self.lines.partition_point(|&start| start <= span.start)
+    }
+
+    /// The line for a step, up to how bowls and dishes changed.
+    fn record(&self, frame: &Frame<N>, position: &Position) -> Option<String> {
+        let (span, kind) = step(position)?;
+        let (ingredients, _) = self.before.as_ref()?;
+
+        let mut line = String::from("{\"recipe\":");
+        string(&mut line, frame.recipe_title());
+        write!(line, ",\"span\":[{},{}]", span.start, span.end).ok()?;
+        write!(line, ",\"line\":{},\"kind\":\"{kind}\"", self.line(span)).ok()?;
+
+        line.push_str(",\"read\":{");
+        let read = reads(position);
+        let mut first = true;
+        for (name, amount) in ingredients {
+            let all_dry = read.is_none() && amount.kind() == IngredientKind::Dry;
+            let named = read
+                .as_ref()
+                .is_some_and(|read| read.iter().any(|read| read.to_lowercase() == *name));
+            if all_dry || named {
+                entry(&mut line, &mut first, name, amount);
+            }
+        }
+
+        line.push_str("},\"written\":{");
+        let after = values(frame);
+        let written = writes(position);
+        let mut first = true;
+        for (name, amount) in &after {
+            let old = ingredients
+                .iter()
+                .find(|(old, _)| old == name)
+                .map(|v| &v.1);
+            if old != Some(amount) || written.is_some_and(|written| written.to_lowercase() == *name)
+            {
+                entry(&mut line, &mut first, name, amount);
+            }
+        }
+        line.push('}');
+        Some(line)
+    }
+
+    /// Finishes `line` with how bowls and dishes changed, and writes it.
+    fn write(&mut self, mut line: String, changes: &[Change<'_, N>]) {
+        line.push_str(",\"bowls\":");
+        diff(&mut line, changes, Container::MixingBowl);
+        line.push_str(",\"dishes\":");
+        diff(&mut line, changes, Container::BakingDish);
+        line.push('}');
+        if let Err(e) = writeln!(self.out, "{line}") {
+            eprintln!("Failed to write trace: {e}");
+            self.failed = true;
+        }
+    }
+}
+
+impl<W: Write, N: Number> Observer<N> for Trace<W, N> {
+    fn before(&mut self, frame: &Frame<N>, position: &Position) {
+        let touched = touches(position)
+            .into_iter()
+            .map(|(container, number)| {
+                (
+                    container,
+                    number,
+                    contents(frame, container, number).to_vec(),
+                )
+            })
+            .collect();
+        self.before = Some((values(frame), touched));
+    }
+
+    fn after(&mut self, frame: &Frame<N>, position: &Position) {
+        if self.failed {
+            return;
+        }
+        let Some(line) = self.record(frame, position) else {
+            return;
+        };
+        let Some((_, touched)) = self.before.take() else {
+            return;
+        };
+        if let Position::Instruction(Spanned(Instruction::ServeWith(_), _)) = position {
+            let bowl = touched.into_iter().next().map(|(.., bowl)| bowl);
+            self.serving.push((line, bowl.unwrap_or_default()));
+            return;
+        }
+        let changes: Vec<_> = touched
+            .iter()
+            .map(|(container, number, old)| {
+                let new = contents(frame, *container, *number);
+                (*container, *number, old.as_slice(), new)
+            })
+            .collect();
+        self.write(line, &changes);
+    }
+
+    fn leave(&mut self, frame: &Frame<N>) {
+        if frame.return_span().is_none() {
+            return;
+        }
+        let Some((line, old)) = self.serving.pop() else {
+            return;
+        };
+        if self.failed {
+            return;
+        }
+        let handed = frame.kitchen().mixing_bowl(1);
+        let new: Vec<_> = old.iter().chain(handed).cloned().collect();
+        self.write(line, &[(Container::MixingBowl, 1, &old, &new)]);
+    }
+}
+
+/// The ingredients of a frame that have values.
+fn values<N: Number>(frame: &Frame<N>) -> Values<N> {
+    frame
+        .ingredients()
+        .into_iter()
+        .filter_map(|(name, kind, amount)| {
+            Some((
+                name.to_string(),
+                IngredientAmount::new(amount?.clone(), kind),
+            ))
+        })
+        .collect()
+}
+
+/// The span of the step at `position` and what kind of step it is, or `None` for the end of a
+/// recipe without `Serves`.
+pub(super) fn step(position: &Position) -> Option<(SimpleSpan, &'static str)> {
+    match position {
+        Position::Instruction(Spanned(instruction, span)) => Some((*span, kind(instruction))),
+        Position::EndOfLoop(Spanned(_, span), _) => Some((*span, "until")),
+        Position::EndOfRecipe(serves) => Some(((*serves)?.1, "serves")),
+    }
+}
+
+fn kind(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Take(_) => "take",
+        Instruction::Put(..) => "put",
+        Instruction::Fold(..) => "fold",
+        Instruction::Add(..) => "add",
+        Instruction::Remove(..) => "remove",
+        Instruction::Combine(..) => "combine",
+        Instruction::Divide(..) => "divide",
+        Instruction::AddDryIngredients(_) => "add_dry_ingredients",
+        Instruction::Liquefy(_) => "liquefy",
+        Instruction::LiquefyContents(_) => "liquefy_contents",
+        Instruction::Stir(..) => "stir",
+        Instruction::StirIngredient(..) => "stir_ingredient",
+        Instruction::Mix(_) => "mix",
+        Instruction::Clean(_) => "clean",
+        Instruction::Pour(..) => "pour",
+        Instruction::VerbLoop(_) => "loop",
+        Instruction::SetAside => "set_aside",
+        Instruction::ServeWith(_) => "serve_with",
+        Instruction::Refrigerate(_) => "refrigerate",
+        Instruction::Serves(_) => "serves",
+    }
+}
+
+/// The ingredients a step reads, or `None` if it reads every dry ingredient.
+fn reads<'a>(position: &Position<'_, 'a>) -> Option<Vec<&'a str>> {
+    let name = match position {
+        Position::Instruction(Spanned(instruction, _)) => match instruction {
+            Instruction::Put(name, _)
+            | Instruction::Add(name, _)
+            | Instruction::Remove(name, _)
+            | Instruction::Combine(name, _)
+            | Instruction::Divide(name, _)
+            | Instruction::Liquefy(name)
+            | Instruction::StirIngredient(name, _) => name,
+            Instruction::VerbLoop(verb_loop) => verb_loop.ingredient,
+            Instruction::AddDryIngredients(_) => return None,
+            _ => return Some(vec![]),
+        },
+        Position::EndOfLoop(_, verb_loop) => {
+            let until = verb_loop.until_ingredient.into_iter();
+            return Some(until.chain([verb_loop.ingredient]).collect());
+        }
+        Position::EndOfRecipe(_) => return Some(vec![]),
+    };
+    Some(vec![name])
+}
+
+/// The bowls and dishes a step may change, by ordinal.
+fn touches(position: &Position) -> Vec<(Container, usize)> {
+    let serves = match position {
+        // The sous-chef's first mixing bowl is emptied into ours when they are done
+        Position::Instruction(Spanned(Instruction::ServeWith(_), _)) => {
+            return vec![(Container::MixingBowl, 1)];
+        }
+        Position::Instruction(Spanned(
+            Instruction::Serves(diners) | Instruction::Refrigerate(Some(diners)),
+            _,
+        ))
+        | Position::EndOfRecipe(Some(Spanned(diners, _))) => *diners,
+        Position::Instruction(Spanned(instruction, _)) => {
+            return journal::container(instruction)
+                .map(|(container, number)| (container, number.max(1)))
+                .into_iter()
+                .collect();
+        }
+        _ => 0,
+    };
+    (1..=serves)
+        .map(|dish| (Container::BakingDish, dish))
+        .collect()
+}
+
+/// An ingredient a step writes even when its value stays the same.
+fn writes<'a>(position: &Position<'_, 'a>) -> Option<&'a str> {
+    match position {
+        Position::Instruction(Spanned(Instruction::Take(name) | Instruction::Fold(name, _), _)) => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+fn entry<N: Number>(line: &mut String, first: &mut bool, name: &str, amount: &IngredientAmount<N>) {
+    if !std::mem::take(first) {
+        line.push(',');
+    }
+    string(line, name);
+    line.push(':');
+    value(line, amount);
+}
+
+fn value<N: Number>(line: &mut String, amount: &IngredientAmount<N>) {
+    match amount.kind() {
+        IngredientKind::Dry => line.push_str(&amount.amount().to_string()),
+        IngredientKind::Wet => line.push_str(&format!("{{\"wet\":{}}}", amount.amount())),
+    }
+}
+
+fn diff<N: Number>(line: &mut String, changes: &[Change<'_, N>], of: Container) {
+    line.push('[');
+    let mut first = true;
+    for &(container, number, old, new) in changes {
+        let at = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        if container != of || (at == old.len() && at == new.len()) {
+            continue;
+        }
+        if !std::mem::take(&mut first) {
+            line.push(',');
+        }
+        line.push_str(&format!("{{\"number\":{number},\"at\":{at},\"removed\":"));
+        list(line, &old[at..]);
+        line.push_str(",\"added\":");
+        list(line, &new[at..]);
+        line.push('}');
+    }
+    line.push(']');
+}
+
+fn contents<N: Number>(
+    frame: &Frame<N>,
+    container: Container,
+    number: usize,
+) -> &[IngredientAmount<N>] {
+    match container {
+        Container::MixingBowl => frame.kitchen().mixing_bowl(number),
+        Container::BakingDish => frame.kitchen().baking_dish(number),
+    }
+}
+
+fn list<N: Number>(line: &mut String, amounts: &[IngredientAmount<N>]) {
+    line.push('[');
+    for (i, amount) in amounts.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        value(line, amount);
+    }
+    line.push(']');
+}
+
+/// Writes `s` as a JSON string.
+fn string(line: &mut String, s: &str) {
+    line.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            c if c.is_control() => line.push_str(&format!("\\u{:04x}", c as u32)),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::interpreter::{Interpreter, MemoryIo};
+
+    /// Lets the test read what was traced after the interpreter is done with it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let source = r#"
+Sweet "Tea".
+
+Ingredients.
+2 g sugar
+72 g salt
+
+Method.
+Put sugar into mixing bowl. Liquefy salt. Stir sugar into the mixing bowl. Fold sugar into mixing bowl. Put salt into the 2nd mixing bowl. Pour contents of the 2nd mixing bowl into the baking dish. Boil the sugar. Put sugar into mixing bowl. Steep the sugar until boiled.
+
+Serves 1.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let out = Shared::default();
+        Interpreter::with_io(MemoryIo::default())
+            .with_observer(Trace::new(out.clone(), source))
+            .run_program(&program)
+            .unwrap();
+
+        let trace = String::from_utf8(out.0.take()).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        let span = |line: &str| {
+            let start = line.find("\"span\":[").unwrap() + 8;
+            line[start..]
+                .split_once(',')
+                .unwrap()
+                .0
+                .parse::<usize>()
+                .unwrap()
+        };
+        assert_eq!(&source[span(lines[0])..][..3], "Put");
+        assert_eq!(
+            lines[0],
+            format!(
+                r#"{{"recipe":"Sweet \"Tea\"","span":[{},{}],"line":8,"kind":"put","read":{{"sugar":2}},"written":{{}},"bowls":[{{"number":1,"at":0,"removed":[],"added":[2]}}],"dishes":[]}}"#,
+                span(lines[0]),
+                span(lines[0]) + 26
+            )
+        );
+        assert!(lines[1].contains(r#""kind":"liquefy","read":{"salt":72},"written":{"salt":{"wet":72}},"bowls":[],"dishes":[]"#));
+        assert!(lines[3].contains(r#""kind":"fold","read":{},"written":{"sugar":2},"bowls":[{"number":1,"at":0,"removed":[2],"added":[]}]"#));
+        assert!(lines[5].contains(r#""kind":"pour","read":{},"written":{},"bowls":[],"dishes":[{"number":1,"at":0,"removed":[],"added":[{"wet":72}]}]"#));
+        assert!(lines[6].contains(r#""kind":"loop","read":{"sugar":2}"#));
+        assert!(lines[8].contains(r#""kind":"until","read":{"sugar":2},"written":{"sugar":1}"#));
+        let last = lines.last().unwrap();
+        assert!(last.contains(r#""line":10,"kind":"serves","read":{},"written":{},"bowls":[],"dishes":[{"number":1,"at":0,"removed":[{"wet":72}],"added":[]}]"#));
+    }
+
+    #[test]
+    fn test_trace_serve_with() {
+        let source = r#"
+Toast.
+
+Ingredients.
+1 slice
+
+Method.
+Put slice into mixing bowl. Serve with butter. Put slice into the 2nd mixing bowl.
+
+Butter.
+
+Ingredients.
+2 g butter
+
+Method.
+Put butter into the mixing bowl. Put butter into the 2nd mixing bowl.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let out = Shared::default();
+        Interpreter::with_io(MemoryIo::default())
+            .with_observer(Trace::new(out.clone(), source))
+            .run_program(&program)
+            .unwrap();
+
+        let trace = String::from_utf8(out.0.take()).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].contains(r#""recipe":"Butter","#));
+        assert!(lines[2].contains(r#""recipe":"Butter","#));
+        // Written once the sous-chef is done, with the bowl they handed back
+        assert!(lines[3].contains(r#""recipe":"Toast","#));
+        assert!(lines[3].contains(r#""kind":"serve_with","read":{},"written":{},"bowls":[{"number":1,"at":1,"removed":[],"added":[1,2]}],"dishes":[]"#));
+        assert!(lines[4]
+            .contains(r#""kind":"put","read":{"slice":1},"written":{},"bowls":[{"number":2,"#));
+    }
+}
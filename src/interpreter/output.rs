@@ -0,0 +1,101 @@
+use super::Number;
+
+/// How served ingredients are written out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Serving {
+    pub invalid_codepoints: InvalidCodepoints,
+    /// Replaces control characters other than newlines and tabs, so recipes cannot send escape
+    /// sequences to a terminal.
+    pub filter_control: bool,
+}
+
+/// What to do with a liquid ingredient whose value is not a Unicode scalar value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCodepoints {
+    /// Fail with [`RuntimeError::InvalidCodepoint`](super::RuntimeError::InvalidCodepoint).
+    #[default]
+    Error,
+    /// Serve U+FFFD REPLACEMENT CHARACTER instead.
+    Replace,
+    /// Serve the value as if it were dry.
+    Number,
+}
+
+impl Serving {
+    /// Serves a dry ingredient.
+    pub(crate) fn dry<N: Number>(&self, output: &mut String, amount: &N) {
+        output.push_str(&amount.to_string());
+        output.push(' ');
+    }
+
+    /// Serves a liquid ingredient, returning `false` if it is not a character and that is an error.
+    pub(crate) fn liquid<N: Number>(&self, output: &mut String, amount: &N) -> bool {
+        match amount.to_u32().and_then(char::from_u32) {
+            Some(c) if self.filter_control && is_blocked(c) => {
+                output.push(char::REPLACEMENT_CHARACTER)
+            }
+            Some(c) => output.push(c),
+            None => match self.invalid_codepoints {
+                InvalidCodepoints::Error => return false,
+                InvalidCodepoints::Replace => output.push(char::REPLACEMENT_CHARACTER),
+                InvalidCodepoints::Number => self.dry(output, amount),
+            },
+        }
+        true
+    }
+}
+
+fn is_blocked(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serve(serving: Serving, amounts: &[i64]) -> Option<String> {
+        let mut output = String::new();
+        for amount in amounts {
+            if !serving.liquid(&mut output, amount) {
+                return None;
+            }
+        }
+        Some(output)
+    }
+
+    #[test]
+    fn test_invalid_codepoints() {
+        let amounts = [72, 0xD800, 105, -1];
+        let policy = |invalid_codepoints| Serving {
+            invalid_codepoints,
+            ..Serving::default()
+        };
+        assert_eq!(serve(policy(InvalidCodepoints::Error), &amounts), None);
+        assert_eq!(
+            serve(policy(InvalidCodepoints::Replace), &amounts).as_deref(),
+            Some("H\u{FFFD}i\u{FFFD}")
+        );
+        assert_eq!(
+            serve(policy(InvalidCodepoints::Number), &amounts).as_deref(),
+            Some("H55296 i-1 ")
+        );
+    }
+
+    #[test]
+    fn test_filter_control() {
+        // ESC [ 2 J clears the screen
+        let amounts = [27, 91, 50, 74, 10, 9, 0x9B, 0x7F, 0x263A];
+        assert_eq!(
+            serve(Serving::default(), &amounts).as_deref(),
+            Some("\u{1b}[2J\n\t\u{9b}\u{7f}\u{263a}")
+        );
+        let serving = Serving {
+            filter_control: true,
+            ..Serving::default()
+        };
+        assert_eq!(
+            serve(serving, &amounts).as_deref(),
+            Some("\u{FFFD}[2J\n\t\u{FFFD}\u{FFFD}\u{263a}")
+        );
+    }
+}
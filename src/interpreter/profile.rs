@@ -0,0 +1,353 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use chumsky::span::SimpleSpan;
+
+use super::{observer, Frame, Observer, Position};
+
+/// How often a method step ran and how long it took altogether.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepProfile {
+    pub span: SimpleSpan,
+    /// The kind of step, named as in a [`Trace`](super::Trace).
+    pub kind: &'static str,
+    pub runs: u64,
+    pub time: Duration,
+}
+
+/// A recipe in a [`Profile`], with the recipes its sous-chefs prepared for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfiledRecipe {
+    pub title: String,
+    /// Times the recipe was started.
+    pub calls: u64,
+    /// Steps of the recipe that ran, in the order they appear in the source.
+    pub steps: Vec<StepProfile>,
+    pub callees: Vec<ProfiledRecipe>,
+}
+
+impl ProfiledRecipe {
+    /// Steps run by the recipe itself.
+    pub fn runs(&self) -> u64 {
+        self.steps.iter().map(|step| step.runs).sum()
+    }
+
+    /// Time spent on the recipe's own steps.
+    pub fn self_time(&self) -> Duration {
+        self.steps.iter().map(|step| step.time).sum()
+    }
+
+    /// Time spent on the recipe, including the recipes it called for.
+    pub fn total_time(&self) -> Duration {
+        self.self_time() + self.callees.iter().map(Self::total_time).sum::<Duration>()
+    }
+}
+
+/// A recipe in the call tree.
+#[derive(Debug)]
+struct Node {
+    title: String,
+    calls: u64,
+    steps: HashMap<SimpleSpan, StepProfile>,
+    callees: Vec<usize>,
+}
+
+/// Counts and times every step an interpreter takes, as an [`Observer`].
+///
+/// Steps are grouped by recipe, with the recipes called for by sous-chefs nested under the recipe
+/// that called for them. A recipe calling for itself, directly or through other recipes, is
+/// counted as the recipe further up instead of being nested ever deeper. The full stacks are kept
+/// for [`write_folded`](Self::write_folded).
+#[derive(Debug)]
+pub struct Profile {
+    /// Offset of the start of each line of the source
+    lines: Vec<usize>,
+    nodes: Vec<Node>,
+    /// Node of each recipe being prepared, the main recipe first
+    stack: Vec<usize>,
+    /// Every recipe title seen, as written in the folded format
+    titles: Vec<String>,
+    title_ids: HashMap<String, usize>,
+    /// Every stack of recipes seen, as the stack below it and the title on top
+    stacks: Vec<(Option<usize>, usize)>,
+    stack_ids: HashMap<(Option<usize>, usize), usize>,
+    /// Index in `stacks` of each recipe being prepared
+    current: Vec<usize>,
+    folded: HashMap<(usize, SimpleSpan), StepProfile>,
+    started: Option<(Instant, SimpleSpan, &'static str)>,
+}
+
+impl Profile {
+    /// Profiles a program parsed from `source`.
+    pub fn new(source: &str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            lines,
+            nodes: Vec::new(),
+            stack: Vec::new(),
+            titles: Vec::new(),
+            title_ids: HashMap::new(),
+            stacks: Vec::new(),
+            stack_ids: HashMap::new(),
+            current: Vec::new(),
+            folded: HashMap::new(),
+            started: None,
+        }
+    }
+
+    /// The line of `span` in the source, counting from 1.
+    pub fn line(&self, span: SimpleSpan) -> usize {
+        self.lines.partition_point(|&start| start <= span.start)
+    }
+
+    /// The main recipe and everything it called for, or `None` if no step was taken.
+    pub fn main(&self) -> Option<ProfiledRecipe> {
+        (!self.nodes.is_empty()).then(|| self.recipe(0))
+    }
+
+    /// Every step that ran in any recipe, the one that took longest first.
+    pub fn hot_steps(&self) -> Vec<StepProfile> {
+        let mut steps: HashMap<SimpleSpan, StepProfile> = HashMap::new();
+        for step in self.nodes.iter().flat_map(|node| node.steps.values()) {
+            steps
+                .entry(step.span)
+                .and_modify(|total| {
+                    total.runs += step.runs;
+                    total.time += step.time;
+                })
+                .or_insert(*step);
+        }
+        let mut steps: Vec<_> = steps.into_values().collect();
+        steps.sort_by(|a, b| b.time.cmp(&a.time).then(a.span.start.cmp(&b.span.start)));
+        steps
+    }
+
+    /// Writes the time spent on each step in the folded stack format taken by flamegraph tools.
+    ///
+    /// Each line is the titles of the recipes being prepared, the main recipe first, then the kind
+    /// and line of the step, all separated by `;`, followed by the nanoseconds spent on it.
+    pub fn write_folded(&self, mut out: impl Write) -> std::io::Result<()> {
+        // A stack is always seen after the stack below it
+        let mut stacks: Vec<String> = Vec::with_capacity(self.stacks.len());
+        for &(below, title) in &self.stacks {
+            let title = &self.titles[title];
+            stacks.push(match below {
+                Some(below) => format!("{};{title}", stacks[below]),
+                None => title.clone(),
+            });
+        }
+        // Steps of the same kind on the same line are told apart by nothing but their span
+        let mut lines: BTreeMap<String, u128> = BTreeMap::new();
+        for ((stack, span), step) in &self.folded {
+            let stack = &stacks[*stack];
+            let line = self.line(*span);
+            let frame = format!("{stack};{} (line {line})", step.kind);
+            *lines.entry(frame).or_default() += step.time.as_nanos();
+        }
+        for (frame, nanos) in lines {
+            writeln!(out, "{frame} {nanos}")?;
+        }
+        Ok(())
+    }
+
+    fn recipe(&self, node: usize) -> ProfiledRecipe {
+        let node = &self.nodes[node];
+        let mut steps: Vec<_> = node.steps.values().copied().collect();
+        steps.sort_by_key(|step| step.span.start);
+        ProfiledRecipe {
+            title: node.title.clone(),
+            calls: node.calls,
+            steps,
+            callees: node.callees.iter().map(|&id| self.recipe(id)).collect(),
+        }
+    }
+
+    fn add_node(&mut self, title: &str) -> usize {
+        self.nodes.push(Node {
+            title: title.to_string(),
+            calls: 0,
+            steps: HashMap::new(),
+            callees: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Index of the stack of titles with `title` on top of the stack `below`.
+    fn stack_id(&mut self, below: Option<usize>, title: &str) -> usize {
+        let title = match self.title_ids.get(title) {
+            Some(&id) => id,
+            None => {
+                // Semicolons separate the titles in the folded format
+                self.titles.push(title.replace(';', ","));
+                self.title_ids
+                    .insert(title.to_string(), self.titles.len() - 1);
+                self.titles.len() - 1
+            }
+        };
+        let stack = (below, title);
+        if let Some(&id) = self.stack_ids.get(&stack) {
+            return id;
+        }
+        self.stacks.push(stack);
+        self.stack_ids.insert(stack, self.stacks.len() - 1);
+        self.stacks.len() - 1
+    }
+}
+
+impl<N> Observer<N> for Profile {
+    fn before(&mut self, frame: &Frame<N>, position: &Position) {
+        if self.stack.is_empty() {
+            let main = self.add_node(frame.recipe_title());
+            self.nodes[main].calls += 1;
+            self.stack.push(main);
+            let stack = self.stack_id(None, frame.recipe_title());
+            self.current.push(stack);
+        }
+        self.started = observer::step(position).map(|(span, kind)| (Instant::now(), span, kind));
+    }
+
+    fn after(&mut self, _frame: &Frame<N>, _position: &Position) {
+        let Some((started, span, kind)) = self.started.take() else {
+            return;
+        };
+        let time = started.elapsed();
+        let (Some(&node), Some(&stack)) = (self.stack.last(), self.current.last()) else {
+            return;
+        };
+        let unrun = StepProfile {
+            span,
+            kind,
+            runs: 0,
+            time: Duration::ZERO,
+        };
+        for step in [
+            self.nodes[node].steps.entry(span).or_insert(unrun),
+            self.folded.entry((stack, span)).or_insert(unrun),
+        ] {
+            step.runs += 1;
+            step.time += time;
+        }
+    }
+
+    fn enter(&mut self, frame: &Frame<N>) {
+        let title = frame.recipe_title();
+        let caller = *self
+            .stack
+            .last()
+            .expect("Sous-chefs are called from a recipe");
+        let on_stack = self
+            .stack
+            .iter()
+            .copied()
+            .find(|&id| self.nodes[id].title == title);
+        let existing = self.nodes[caller]
+            .callees
+            .iter()
+            .copied()
+            .find(|&id| self.nodes[id].title == title);
+        let node = match on_stack.or(existing) {
+            Some(node) => node,
+            None => {
+                let node = self.add_node(title);
+                self.nodes[caller].callees.push(node);
+                node
+            }
+        };
+        self.nodes[node].calls += 1;
+        self.stack.push(node);
+        let stack = self.stack_id(self.current.last().copied(), title);
+        self.current.push(stack);
+    }
+
+    fn leave(&mut self, _frame: &Frame<N>) {
+        self.stack.pop();
+        self.current.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::interpreter::{Interpreter, MemoryIo};
+
+    #[test]
+    fn test_profile() {
+        let source = r#"
+Pancakes.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Serve with syrup. Serve with syrup.
+
+Serves 1.
+
+Syrup.
+
+Ingredients.
+2 g sugar
+
+Method.
+Put sugar into mixing bowl. Serve with brown sugar.
+
+Brown Sugar.
+
+Ingredients.
+1 g sugar
+
+Method.
+Stir for 1 minute.
+"#
+        .trim();
+        let program = crate::parser::parse_recipe(source);
+        let profile = Rc::new(RefCell::new(Profile::new(source)));
+        Interpreter::with_io(MemoryIo::default())
+            .with_observer(Rc::clone(&profile))
+            .run_program(&program)
+            .unwrap();
+        let profile = profile.borrow();
+
+        let main = profile.main().unwrap();
+        let counts = |recipe: &ProfiledRecipe| {
+            let runs: Vec<_> = recipe.steps.iter().map(|step| step.runs).collect();
+            (recipe.title.clone(), recipe.calls, runs)
+        };
+        assert_eq!(counts(&main), ("Pancakes".to_string(), 1, vec![1, 1, 1, 1]));
+        let syrup = &main.callees[0];
+        assert_eq!(counts(syrup), ("Syrup".to_string(), 2, vec![2, 2]));
+        let sugar = &syrup.callees[0];
+        assert_eq!(counts(sugar), ("Brown Sugar".to_string(), 2, vec![2]));
+        assert!(sugar.callees.is_empty());
+        assert_eq!(main.callees.len(), 1);
+
+        let hot: u64 = profile.hot_steps().iter().map(|step| step.runs).sum();
+        assert_eq!(hot, 10);
+
+        let mut folded = Vec::new();
+        profile.write_folded(&mut folded).unwrap();
+        let stacks: Vec<_> = String::from_utf8(folded)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            stacks,
+            [
+                "Pancakes;Syrup;Brown Sugar;stir (line 25)",
+                "Pancakes;Syrup;put (line 17)",
+                "Pancakes;Syrup;serve_with (line 17)",
+                "Pancakes;put (line 7)",
+                "Pancakes;serve_with (line 7)",
+                "Pancakes;serves (line 9)",
+            ]
+        );
+    }
+}
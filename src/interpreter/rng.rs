@@ -0,0 +1,94 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// Source of randomness for `Mix`.
+pub trait ChefRng {
+    /// Picks an index below `len`, which is never 0.
+    fn index(&mut self, len: usize) -> usize;
+
+    /// State of a [`SeededRng`] to carry on from in a [`Snapshot`](super::Snapshot), `None` for
+    /// generators that cannot be saved.
+    fn state(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Shuffles `items` in place with Fisher-Yates, picking indices from `rng`.
+pub(crate) fn shuffle<T>(rng: &mut dyn ChefRng, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = rng.index(i + 1);
+        items.swap(i, j);
+    }
+}
+
+/// A small, fast generator (SplitMix64) that gives the same sequence for the same seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from the randomly keyed std hasher, so every run mixes differently.
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl ChefRng for SeededRng {
+    fn index(&mut self, len: usize) -> usize {
+        // Maps the full u64 range onto 0..len, which is unbiased enough for any bowl that fits in memory
+        ((u128::from(self.next_u64()) * len as u128) >> 64) as usize
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+}
+
+/// Never moves anything, so `Mix` leaves a bowl as it is. Meant for checking the output of recipes in tests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoShuffle;
+
+impl ChefRng for NoShuffle {
+    fn index(&mut self, len: usize) -> usize {
+        len - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_shuffle() {
+        let shuffled = |seed| {
+            let mut items = (0..20).collect::<Vec<_>>();
+            shuffle(&mut SeededRng::new(seed), &mut items);
+            items
+        };
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), shuffled(43));
+        assert_ne!(shuffled(42), (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_no_shuffle() {
+        let mut items = [1, 2, 3, 4];
+        shuffle(&mut NoShuffle, &mut items);
+        assert_eq!(items, [1, 2, 3, 4]);
+    }
+}
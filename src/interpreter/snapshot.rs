@@ -0,0 +1,444 @@
+use std::fmt::Display;
+
+use chumsky::span::SimpleSpan;
+
+use super::{EvalContext, Frame, IngredientAmount, Kitchen, Number};
+use crate::parser::{ChefProgram, IngredientKind};
+
+/// Version of the snapshot format, raised whenever it changes.
+const VERSION: u32 = 1;
+
+/// Everything needed to carry on preparing a program later, taken with
+/// [`Interpreter::snapshot`](super::Interpreter::snapshot).
+///
+/// Written as lines of text with [`Display`], and read back with [`Snapshot::parse`]. A snapshot
+/// only resumes the program it was taken from, so it is tied to a hash of the program's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<N = i64> {
+    source_hash: u64,
+    rng: Option<u64>,
+    unread: Vec<char>,
+    frames: Vec<SavedFrame<N>>,
+}
+
+/// A [`Frame`] with its ingredients and kitchen in a fixed order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SavedFrame<N> {
+    recipe: Option<String>,
+    return_span: Option<SimpleSpan>,
+    pc: Vec<usize>,
+    ingredients: Vec<(String, IngredientKind, Option<IngredientAmount<N>>)>,
+    bowls: Vec<(usize, Vec<IngredientAmount<N>>)>,
+    dishes: Vec<(usize, Vec<IngredientAmount<N>>)>,
+}
+
+/// Why a snapshot could not be read or resumed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The text does not start like a snapshot.
+    NotASnapshot,
+    /// Written by a version of spatula with another snapshot format.
+    UnsupportedVersion(u32),
+    /// A line, counting from 1, that could not be read.
+    Malformed { line: usize, reason: String },
+    /// Taken from another program, or the recipe was edited since.
+    EditedSource,
+    /// A recipe is at a step the program does not have.
+    InvalidPosition { recipe: String },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "Not a spatula snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Snapshot version {version} is not supported, expected version {VERSION}"
+            ),
+            SnapshotError::Malformed { line, reason } => {
+                write!(f, "Malformed snapshot on line {line}: {reason}")
+            }
+            SnapshotError::EditedSource => {
+                write!(f, "The recipe has changed since the snapshot was taken")
+            }
+            SnapshotError::InvalidPosition { recipe } => {
+                write!(f, "Snapshot is at a step that `{recipe}` does not have")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl<N: Number> Snapshot<N> {
+    pub(crate) fn new(
+        source: &str,
+        rng: Option<u64>,
+        unread: Vec<char>,
+        frames: &[Frame<N>],
+    ) -> Self {
+        Self {
+            source_hash: hash(source),
+            rng,
+            unread,
+            frames: frames.iter().map(SavedFrame::new).collect(),
+        }
+    }
+
+    /// State of the generator mixing bowls, `None` if it could not be saved.
+    pub(crate) fn rng(&self) -> Option<u64> {
+        self.rng
+    }
+
+    pub(crate) fn unread(&self) -> &[char] {
+        &self.unread
+    }
+
+    /// The frames of the snapshot, if it was taken from `source` and fits `program`.
+    pub(crate) fn frames(
+        &self,
+        program: &ChefProgram,
+        source: &str,
+    ) -> Result<Vec<Frame<N>>, SnapshotError> {
+        if self.source_hash != hash(source) {
+            return Err(SnapshotError::EditedSource);
+        }
+        self.frames
+            .iter()
+            .map(|saved| saved.to_frame(program))
+            .collect()
+    }
+
+    /// Reads a snapshot written with [`Display`].
+    pub fn parse(input: &str) -> Result<Self, SnapshotError> {
+        let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let version = match lines.next() {
+            Some((_, line)) => line
+                .strip_prefix("spatula snapshot ")
+                .ok_or(SnapshotError::NotASnapshot)?,
+            None => return Err(SnapshotError::NotASnapshot),
+        };
+        match version.parse() {
+            Ok(VERSION) => {}
+            Ok(version) => return Err(SnapshotError::UnsupportedVersion(version)),
+            Err(_) => return Err(SnapshotError::NotASnapshot),
+        }
+
+        let mut snapshot = Snapshot {
+            source_hash: 0,
+            rng: None,
+            unread: vec![],
+            frames: vec![],
+        };
+        for (number, line) in lines {
+            let malformed = |reason: &str| SnapshotError::Malformed {
+                line: number,
+                reason: reason.to_string(),
+            };
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let frame = snapshot.frames.last_mut();
+            match (key, frame) {
+                ("source", _) => {
+                    snapshot.source_hash =
+                        u64::from_str_radix(rest, 16).map_err(|_| malformed("Expected a hash"))?
+                }
+                ("rng", _) if rest == "none" => snapshot.rng = None,
+                ("rng", _) => {
+                    snapshot.rng = Some(rest.parse().map_err(|_| malformed("Expected a state"))?)
+                }
+                ("unread", _) => {
+                    snapshot.unread = rest
+                        .split_whitespace()
+                        .map(|c| c.parse().ok().and_then(char::from_u32))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| malformed("Expected character codes"))?
+                }
+                ("frame", _) => snapshot.frames.push(SavedFrame {
+                    recipe: None,
+                    return_span: None,
+                    pc: vec![0],
+                    ingredients: vec![],
+                    bowls: vec![],
+                    dishes: vec![],
+                }),
+                (_, None) => return Err(malformed("Expected a frame first")),
+                ("recipe", Some(frame)) => frame.recipe = Some(rest.to_string()),
+                ("called", Some(frame)) => {
+                    let span = numbers(rest).ok_or_else(|| malformed("Expected a span"))?;
+                    let [start, end] = span[..] else {
+                        return Err(malformed("Expected a span"));
+                    };
+                    frame.return_span = Some(SimpleSpan::new(start, end));
+                }
+                ("pc", Some(frame)) => {
+                    frame.pc = numbers(rest)
+                        .filter(|pc| !pc.is_empty())
+                        .ok_or_else(|| malformed("Expected instruction indices"))?
+                }
+                ("ingredient", Some(frame)) => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let (Some(kind), Some(value), Some(name)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        return Err(malformed("Expected a kind, value and name"));
+                    };
+                    let kind = parse_kind(kind).ok_or_else(|| malformed("Expected dry or wet"))?;
+                    let value = match value {
+                        "-" => None,
+                        value => {
+                            Some(parse_amount(value).ok_or_else(|| malformed("Expected a value"))?)
+                        }
+                    };
+                    frame.ingredients.push((name.to_string(), kind, value));
+                }
+                (container @ ("bowl" | "dish"), Some(frame)) => {
+                    let mut parts = rest.split_whitespace();
+                    let ordinal = parts
+                        .next()
+                        .and_then(|ordinal| ordinal.parse().ok())
+                        .ok_or_else(|| malformed("Expected an ordinal"))?;
+                    let contents = parts
+                        .map(parse_amount)
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| malformed("Expected values"))?;
+                    match container {
+                        "bowl" => frame.bowls.push((ordinal, contents)),
+                        _ => frame.dishes.push((ordinal, contents)),
+                    }
+                }
+                _ => return Err(malformed(&format!("Unknown entry `{key}`"))),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+impl<N: Number> Display for Snapshot<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "spatula snapshot {VERSION}")?;
+        writeln!(f, "source {:016x}", self.source_hash)?;
+        match self.rng {
+            Some(state) => writeln!(f, "rng {state}")?,
+            None => writeln!(f, "rng none")?,
+        }
+        write!(f, "unread")?;
+        for c in &self.unread {
+            write!(f, " {}", *c as u32)?;
+        }
+        writeln!(f)?;
+        for frame in &self.frames {
+            writeln!(f, "frame")?;
+            if let Some(recipe) = &frame.recipe {
+                writeln!(f, "recipe {recipe}")?;
+            }
+            if let Some(span) = frame.return_span {
+                writeln!(f, "called {} {}", span.start, span.end)?;
+            }
+            let pc: Vec<_> = frame.pc.iter().map(usize::to_string).collect();
+            writeln!(f, "pc {}", pc.join(" "))?;
+            for (name, kind, value) in &frame.ingredients {
+                let value = value.as_ref().map_or("-".to_string(), write_amount);
+                writeln!(f, "ingredient {} {value} {name}", write_kind(*kind))?;
+            }
+            for (container, contents) in [("bowl", &frame.bowls), ("dish", &frame.dishes)] {
+                for (ordinal, contents) in contents {
+                    write!(f, "{container} {ordinal}")?;
+                    for amount in contents {
+                        write!(f, " {}", write_amount(amount))?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<N: Number> SavedFrame<N> {
+    fn new(frame: &Frame<N>) -> Self {
+        let mut ingredients: Vec<_> = frame
+            .ctx
+            .kinds
+            .iter()
+            .map(|(name, kind)| (name.clone(), *kind, frame.ctx.values.get(name).cloned()))
+            .collect();
+        ingredients.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        let contents = |containers: Vec<(usize, &[IngredientAmount<N>])>| {
+            containers
+                .into_iter()
+                .map(|(ordinal, contents)| (ordinal, contents.to_vec()))
+                .collect()
+        };
+        Self {
+            recipe: frame.recipe_key().map(str::to_string),
+            return_span: frame.return_span(),
+            pc: frame.pc().to_vec(),
+            ingredients,
+            bowls: contents(frame.kitchen().mixing_bowls()),
+            dishes: contents(frame.kitchen().baking_dishes()),
+        }
+    }
+
+    fn to_frame(&self, program: &ChefProgram) -> Result<Frame<N>, SnapshotError> {
+        let recipe = match &self.recipe {
+            Some(key) => match program.auxilary.get(key) {
+                Some(recipe) => recipe,
+                None => {
+                    let recipe = key.clone();
+                    return Err(SnapshotError::InvalidPosition { recipe });
+                }
+            },
+            None => &program.main,
+        };
+        let title = recipe.title;
+
+        let mut kitchen = Kitchen::new();
+        for (ordinal, contents) in &self.bowls {
+            kitchen.bowl_mut(*ordinal).extend(contents.iter().cloned());
+        }
+        for (ordinal, contents) in &self.dishes {
+            kitchen.dish_mut(*ordinal).extend(contents.iter().cloned());
+        }
+        let mut ctx = EvalContext {
+            kinds: Default::default(),
+            values: Default::default(),
+            declared: EvalContext::<N>::declared(recipe),
+            kitchen,
+        };
+        for (name, kind, value) in &self.ingredients {
+            ctx.kinds.insert(name.clone(), *kind);
+            if let Some(value) = value {
+                ctx.values.insert(name.clone(), value.clone());
+            }
+        }
+
+        let mut frame = Frame::new(self.recipe.clone(), title, self.return_span, ctx);
+        frame.set_pc(self.pc.clone());
+        match frame.fits(program) {
+            true => Ok(frame),
+            false => Err(SnapshotError::InvalidPosition {
+                recipe: title.to_string(),
+            }),
+        }
+    }
+}
+
+/// FNV-1a, which unlike the std hashers gives the same hash on every platform and release.
+fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn numbers(input: &str) -> Option<Vec<usize>> {
+    input.split_whitespace().map(|n| n.parse().ok()).collect()
+}
+
+fn write_kind(kind: IngredientKind) -> &'static str {
+    match kind {
+        IngredientKind::Dry => "dry",
+        IngredientKind::Wet => "wet",
+    }
+}
+
+fn parse_kind(kind: &str) -> Option<IngredientKind> {
+    match kind {
+        "dry" => Some(IngredientKind::Dry),
+        "wet" => Some(IngredientKind::Wet),
+        _ => None,
+    }
+}
+
+/// An amount as `kind:value`, such as `wet:72`.
+fn write_amount<N: Number>(amount: &IngredientAmount<N>) -> String {
+    format!("{}:{}", write_kind(amount.kind()), amount.amount())
+}
+
+fn parse_amount<N: Number>(amount: &str) -> Option<IngredientAmount<N>> {
+    let (kind, value) = amount.split_once(':')?;
+    Some(IngredientAmount::new(N::parse(value)?, parse_kind(kind)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, MemoryIo, Status};
+
+    const SOURCE: &str = r#"Snapshot Soup.
+
+Ingredients.
+5 potatoes
+1 cup salt
+10 ml water
+
+Method.
+Put salt into mixing bowl. Put water into mixing bowl.
+Chop the potatoes. Put potatoes into mixing bowl. Serve with stock. Mix the mixing bowl well. Chop the potatoes until chopped.
+Liquefy water. Pour contents of the mixing bowl into the baking dish.
+
+Serves 1.
+
+Stock.
+
+Ingredients.
+2 onions
+
+Method.
+Put onions into mixing bowl. Add onions."#;
+
+    fn interpreter(seed: u64) -> Interpreter<MemoryIo> {
+        Interpreter::with_io(MemoryIo::default()).with_seed(seed)
+    }
+
+    #[test]
+    fn test_resume_after_every_step() {
+        let program = crate::parser::parse_recipe(SOURCE);
+        let mut uninterrupted = interpreter(7);
+        uninterrupted.start(&program).unwrap();
+        let mut steps = 0;
+        while let Status::Running = uninterrupted.step(&program).unwrap() {
+            steps += 1;
+        }
+        let expected = uninterrupted.io().output().to_string();
+
+        for taken in 0..steps {
+            let mut first = interpreter(7);
+            first.start(&program).unwrap();
+            for _ in 0..taken {
+                first.step(&program).unwrap();
+            }
+            let written = first.snapshot(SOURCE).to_string();
+            let snapshot = Snapshot::parse(&written).unwrap();
+            assert_eq!(snapshot, first.snapshot(SOURCE));
+
+            // Mixed with a different seed unless the saved generator is used
+            let mut second = interpreter(8);
+            second.resume(&program, SOURCE, &snapshot).unwrap();
+            while let Status::Running = second.step(&program).unwrap() {}
+            let output = format!("{}{}", first.io().output(), second.io().output());
+            assert_eq!(output, expected, "Resumed after {taken} steps");
+        }
+    }
+
+    #[test]
+    fn test_edited_source_is_refused() {
+        let program = crate::parser::parse_recipe(SOURCE);
+        let mut interpreter = interpreter(7);
+        interpreter.start(&program).unwrap();
+        let snapshot = interpreter.snapshot(SOURCE);
+
+        let edited = SOURCE.replace("5 potatoes", "6 potatoes");
+        assert_eq!(
+            interpreter.resume(&program, &edited, &snapshot),
+            Err(SnapshotError::EditedSource)
+        );
+
+        let written = snapshot
+            .to_string()
+            .replace("spatula snapshot 1", "spatula snapshot 2");
+        assert_eq!(
+            Snapshot::<i64>::parse(&written),
+            Err(SnapshotError::UnsupportedVersion(2))
+        );
+    }
+}
@@ -0,0 +1,585 @@
+use chumsky::span::SimpleSpan;
+
+use super::{
+    bytecode::{Bowl, Bytecode, CompiledRecipe, Op, RecipeId, Slot},
+    rng, stir, ChefIo, IngredientAmount, Interpreter, Kitchen, Number, Operation, RuntimeError,
+    StackFrame, TracedError, Usage,
+};
+use crate::parser::IngredientKind;
+
+/// Mixing bowls or baking dishes by index, `None` for those never used.
+#[derive(Debug, Clone)]
+struct Shelf<N>(Vec<Option<Vec<IngredientAmount<N>>>>);
+
+impl<N> Shelf<N> {
+    fn get(&self, index: usize) -> &[IngredientAmount<N>] {
+        match self.0.get(index) {
+            Some(Some(contents)) => contents,
+            _ => &[],
+        }
+    }
+
+    /// Contents at `index`, which counts as used from now on.
+    fn get_mut(&mut self, index: usize) -> &mut Vec<IngredientAmount<N>> {
+        if index >= self.0.len() {
+            self.0.resize_with(index + 1, || None);
+        }
+        self.0[index].get_or_insert_with(Vec::new)
+    }
+
+    fn items(&self) -> usize {
+        self.0.iter().flatten().map(Vec::len).sum()
+    }
+}
+
+/// The kitchen of a chef on the VM.
+#[derive(Debug, Clone)]
+struct Shelves<N> {
+    bowls: Shelf<N>,
+    dishes: Shelf<N>,
+}
+
+impl<N: Clone> Shelves<N> {
+    fn items(&self) -> usize {
+        self.bowls.items() + self.dishes.items()
+    }
+
+    /// Empties the first `diners` baking dishes, like [`Kitchen::serve`].
+    fn serve(&mut self, diners: usize) -> Vec<IngredientAmount<N>> {
+        let mut served = vec![];
+        for dish in self.dishes.0.iter_mut().take(diners).flatten() {
+            served.extend(std::mem::take(dish).into_iter().rev());
+        }
+        served
+    }
+
+    fn into_kitchen(self) -> Kitchen<N> {
+        let mut kitchen = Kitchen::new();
+        for (index, bowl) in self.bowls.0.into_iter().enumerate() {
+            if let Some(bowl) = bowl {
+                kitchen.bowl_mut(index + 1).extend(bowl);
+            }
+        }
+        for (index, dish) in self.dishes.0.into_iter().enumerate() {
+            if let Some(dish) = dish {
+                kitchen.dish_mut(index + 1).extend(dish);
+            }
+        }
+        kitchen
+    }
+}
+
+/// A recipe being prepared on the VM.
+struct VmFrame<N> {
+    recipe: RecipeId,
+    /// Index of the next op
+    pc: usize,
+    values: Vec<Option<IngredientAmount<N>>>,
+    kitchen: Shelves<N>,
+    call_span: Option<SimpleSpan>,
+}
+
+impl<N: Number> VmFrame<N> {
+    /// Measures out the ingredients of a recipe about to be prepared in `kitchen`.
+    fn new(
+        compiled: &CompiledRecipe,
+        recipe: RecipeId,
+        kitchen: Shelves<N>,
+        call_span: Option<SimpleSpan>,
+    ) -> Result<Self, RuntimeError> {
+        let mut values = vec![None; compiled.slots.len()];
+        for &(slot, value, kind, span) in &compiled.measures {
+            let Some(value) = N::from_usize(value) else {
+                return Err(RuntimeError::InitialValueTooLarge { value, span });
+            };
+            values[slot] = Some(IngredientAmount::new(value, kind));
+        }
+        Ok(Self {
+            recipe,
+            pc: 0,
+            values,
+            kitchen,
+            call_span,
+        })
+    }
+
+    fn value(
+        &self,
+        compiled: &CompiledRecipe,
+        slot: Slot,
+        span: &SimpleSpan,
+    ) -> Result<&IngredientAmount<N>, RuntimeError> {
+        match &self.values[slot.index] {
+            Some(value) => Ok(value),
+            None => Err(missing(compiled, slot, span)),
+        }
+    }
+
+    fn value_mut(
+        &mut self,
+        compiled: &CompiledRecipe,
+        slot: Slot,
+        span: &SimpleSpan,
+    ) -> Result<&mut IngredientAmount<N>, RuntimeError> {
+        match &mut self.values[slot.index] {
+            Some(value) => Ok(value),
+            None => Err(missing(compiled, slot, span)),
+        }
+    }
+}
+
+/// What to do after an op.
+enum Flow {
+    Next,
+    /// The recipe has ended.
+    Return,
+}
+
+impl<I: ChefIo, N: Number> Interpreter<I, N> {
+    /// Runs a program compiled with [`compile`](super::compile), with the same results as
+    /// [`run_program`](Self::run_program) on the program it was compiled from.
+    ///
+    /// Steps are not shown to an [`Observer`](super::Observer) or journaled, and the recipes
+    /// being prepared are not kept in [`frames`](Self::frames).
+    pub fn run_bytecode(&mut self, bytecode: &Bytecode) -> Result<Kitchen<N>, TracedError> {
+        self.usage = Usage::start(&self.limits);
+        let recipes = bytecode.recipes();
+        let kitchen = Shelves {
+            bowls: Shelf(Vec::new()),
+            dishes: Shelf(Vec::new()),
+        };
+        let mut frame =
+            VmFrame::new(&recipes[0], 0, kitchen, None).map_err(|error| TracedError {
+                error,
+                backtrace: vec![StackFrame {
+                    title: recipes[0].title.to_string(),
+                    call_span: None,
+                }],
+            })?;
+        let mut callers = Vec::new();
+        match self.execute(recipes, &mut frame, &mut callers) {
+            Ok(()) => Ok(frame.kitchen.into_kitchen()),
+            Err(error) => {
+                let backtrace = std::iter::once(&frame)
+                    .chain(callers.iter().rev())
+                    .map(|frame| StackFrame {
+                        title: recipes[frame.recipe].title.to_string(),
+                        call_span: frame.call_span,
+                    })
+                    .collect();
+                Err(TracedError { error, backtrace })
+            }
+        }
+    }
+
+    /// Runs ops until the main recipe ends, which leaves it in `frame`.
+    fn execute(
+        &mut self,
+        recipes: &[CompiledRecipe],
+        frame: &mut VmFrame<N>,
+        callers: &mut Vec<VmFrame<N>>,
+    ) -> Result<(), RuntimeError> {
+        loop {
+            let recipe = &recipes[frame.recipe];
+            let span = &recipe.spans[frame.pc];
+            let flow = match recipe.code[frame.pc] {
+                Op::Call(callee) => {
+                    self.usage.step(&self.limits, span)?;
+                    let kitchen = frame.kitchen.clone();
+                    let sous_chef = VmFrame::new(&recipes[callee], callee, kitchen, Some(*span))?;
+                    self.usage
+                        .enter(&self.limits, frame.kitchen.items(), span)?;
+                    frame.pc += 1;
+                    callers.push(std::mem::replace(frame, sous_chef));
+                    Flow::Next
+                }
+                op => self.execute_op(recipe, op, span, frame)?,
+            };
+            if let Flow::Return = flow {
+                let Some(caller) = callers.pop() else {
+                    return Ok(());
+                };
+                // Hands the first mixing bowl back to the chef that called for the recipe
+                let finished = std::mem::replace(frame, caller);
+                let items = frame.kitchen.items();
+                self.usage.leave(items);
+                let first_bowl = finished.kitchen.bowls.0.into_iter().next().flatten();
+                frame
+                    .kitchen
+                    .bowls
+                    .get_mut(0)
+                    .extend(first_bowl.unwrap_or_default());
+            }
+        }
+    }
+
+    /// Runs an op of `recipe` other than a call.
+    fn execute_op(
+        &mut self,
+        recipe: &CompiledRecipe,
+        op: Op,
+        span: &SimpleSpan,
+        frame: &mut VmFrame<N>,
+    ) -> Result<Flow, RuntimeError> {
+        match op {
+            Op::End(diners) => {
+                self.check_frame_return(frame)?;
+                if let Some(diners) = diners {
+                    self.serve_ingredients(&frame.kitchen.serve(diners), span)?;
+                }
+                return Ok(Flow::Return);
+            }
+            _ => self.usage.step(&self.limits, span)?,
+        }
+        frame.pc += 1;
+        match op {
+            Op::Take(slot) => {
+                let Some(value) = self.read_input(span)? else {
+                    self.check_frame_return(frame)?;
+                    return Ok(Flow::Return);
+                };
+                let Some(kind) = recipe.slots[slot.index].1 else {
+                    return Err(missing(recipe, slot, span));
+                };
+                frame.values[slot.index] = Some(IngredientAmount::new(value, kind));
+                return Ok(Flow::Next);
+            }
+            Op::Loop { ingredient, end } => {
+                if frame.value(recipe, ingredient, span)?.amount().is_zero() {
+                    frame.pc = end;
+                }
+                return Ok(Flow::Next);
+            }
+            Op::Until {
+                ingredient,
+                until,
+                body,
+            } => {
+                if let Some(until) = until {
+                    let one = N::from_usize(1).expect("Every number type has a one");
+                    let value = frame.value_mut(recipe, until, span)?;
+                    let decremented = value
+                        .amount()
+                        .apply(Operation::Subtract, &one, self.overflow)
+                        .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+                    value.set_amount(decremented);
+                }
+                if !frame.value(recipe, ingredient, span)?.amount().is_zero() {
+                    frame.pc = body;
+                }
+                return Ok(Flow::Next);
+            }
+            Op::Jump(target) => {
+                frame.pc = target;
+                return Ok(Flow::Next);
+            }
+            Op::Return => {
+                self.check_frame_return(frame)?;
+                return Ok(Flow::Return);
+            }
+            Op::Refrigerate(hours) => {
+                self.check_frame_return(frame)?;
+                if let Some(diners) = hours {
+                    self.serve_ingredients(&frame.kitchen.serve(diners), span)?;
+                }
+                return Ok(Flow::Return);
+            }
+            Op::UndefinedRecipe(name) => {
+                return Err(RuntimeError::UndefinedRecipe {
+                    name: name.to_string(),
+                    span: *span,
+                })
+            }
+            Op::Put(slot, bowl) => {
+                let value = frame.value(recipe, slot, span)?.clone();
+                frame.kitchen.bowls.get_mut(bowl.index).push(value);
+            }
+            Op::Fold(slot, bowl) => {
+                let Some(kind) = recipe.slots[slot.index].1 else {
+                    return Err(missing(recipe, slot, span));
+                };
+                let Some(top) = frame.kitchen.bowls.get_mut(bowl.index).pop() else {
+                    return Err(empty(bowl, span));
+                };
+                // Folding gives an ingredient a value even if it was declared without one
+                match &mut frame.values[slot.index] {
+                    Some(value) => value.set_amount(top.amount),
+                    value @ None => *value = Some(IngredientAmount::new(top.amount, kind)),
+                }
+            }
+            Op::Arithmetic(operation, slot, bowl) => {
+                let Some(value) = &frame.values[slot.index] else {
+                    return Err(missing(recipe, slot, span));
+                };
+                let contents = frame.kitchen.bowls.get_mut(bowl.index);
+                let Some(top) = contents.last() else {
+                    return Err(empty(bowl, span));
+                };
+                let amount = top
+                    .amount
+                    .apply(operation, &value.amount, self.overflow)
+                    .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+                contents.push(IngredientAmount::new(amount, value.kind));
+            }
+            Op::AddDryIngredients(bowl) => {
+                let mut dry_ingredients = N::from_usize(0).expect("Every number type has a zero");
+                for value in frame.values.iter().flatten() {
+                    if value.kind == IngredientKind::Dry {
+                        dry_ingredients = dry_ingredients
+                            .apply(Operation::Add, &value.amount, self.overflow)
+                            .map_err(|e| RuntimeError::arithmetic(e, *span))?;
+                    }
+                }
+                frame
+                    .kitchen
+                    .bowls
+                    .get_mut(bowl.index)
+                    .push(IngredientAmount::new(dry_ingredients, IngredientKind::Dry));
+            }
+            Op::Liquefy(slot) => {
+                frame.value_mut(recipe, slot, span)?.kind = IngredientKind::Wet;
+            }
+            Op::LiquefyContents(bowl) => {
+                for ingredient in frame.kitchen.bowls.get_mut(bowl.index) {
+                    ingredient.kind = IngredientKind::Wet;
+                }
+            }
+            Op::Stir(bowl, minutes) => {
+                let contents = frame.kitchen.bowls.get_mut(bowl.index);
+                stir(contents, bowl.ordinal, minutes, span)?;
+            }
+            Op::StirIngredient(slot, bowl) => {
+                let value = frame.value(recipe, slot, span)?.amount();
+                let Some(minutes) = value.to_usize() else {
+                    return Err(RuntimeError::InvalidMinutes {
+                        value: value.to_string(),
+                        span: *span,
+                    });
+                };
+                let contents = frame.kitchen.bowls.get_mut(bowl.index);
+                stir(contents, bowl.ordinal, minutes, span)?;
+            }
+            Op::Mix(bowl) => {
+                let contents = frame.kitchen.bowls.get_mut(bowl.index);
+                rng::shuffle(self.rng.as_mut(), contents);
+            }
+            Op::Clean(bowl) => frame.kitchen.bowls.get_mut(bowl.index).clear(),
+            Op::Pour(bowl, dish) => {
+                let Shelves { bowls, dishes } = &mut frame.kitchen;
+                dishes
+                    .get_mut(dish)
+                    .extend_from_slice(bowls.get(bowl.index));
+            }
+            Op::Serves(diners) => {
+                self.serve_ingredients(&frame.kitchen.serve(diners), span)?;
+            }
+            Op::Call(_) | Op::End(_) => unreachable!("Handled by execute"),
+        }
+        self.check_items(frame, span)?;
+        Ok(Flow::Next)
+    }
+
+    /// Checks that the chef that called for `frame` can hold its first mixing bowl, before the
+    /// recipe ends.
+    fn check_frame_return(&self, frame: &VmFrame<N>) -> Result<(), RuntimeError> {
+        let Some(span) = frame.call_span else {
+            return Ok(());
+        };
+        // The caller's ingredients are counted until the sous-chef is done
+        let handed = frame.kitchen.bowls.get(0).len();
+        self.usage.check_items(&self.limits, handed, &span)
+    }
+
+    fn check_items(&self, frame: &VmFrame<N>, span: &SimpleSpan) -> Result<(), RuntimeError> {
+        // Counting is skipped when there is no limit, since the shelves are walked to count
+        match self.limits.max_items {
+            Some(_) => self
+                .usage
+                .check_items(&self.limits, frame.kitchen.items(), span),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The error for an ingredient without a value.
+fn missing(recipe: &CompiledRecipe, slot: Slot, span: &SimpleSpan) -> RuntimeError {
+    let name = slot.name.to_string();
+    let span = *span;
+    match recipe.slots[slot.index].1 {
+        Some(_) => RuntimeError::UninitializedIngredient { name, span },
+        None => RuntimeError::UndefinedIngredient { name, span },
+    }
+}
+
+fn empty(bowl: Bowl, span: &SimpleSpan) -> RuntimeError {
+    RuntimeError::EmptyBowl {
+        bowl: bowl.ordinal,
+        span: *span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{compile, EndOfInput, Input, Limits, MemoryIo};
+
+    /// Runs `source` on both the tree-walking interpreter and the VM, which should agree.
+    fn same(source: &str, input: &str, limits: Limits) -> Result<Kitchen, TracedError> {
+        let program = crate::parser::parse_recipe(source);
+        let interpreter = || {
+            Interpreter::with_io(MemoryIo::new(input))
+                .with_seed(7)
+                .with_limits(limits)
+                .with_input(Input {
+                    end_of_input: EndOfInput::Stop,
+                    ..Input::default()
+                })
+        };
+        let mut tree = interpreter();
+        let expected = tree.run_program(&program);
+        let mut vm = interpreter();
+        let result = vm.run_bytecode(&compile(&program));
+        assert_eq!(result, expected);
+        assert_eq!(vm.io().output(), tree.io().output());
+        result
+    }
+
+    #[test]
+    fn test_loops_and_sous_chefs() {
+        let source = r#"
+Countdown.
+
+Ingredients.
+3 eggs
+2 g flour
+yeast
+
+Method.
+Put eggs into the 2nd mixing bowl. Serve with egg timer. Put eggs into mixing bowl.
+Sift the flour. Knead the eggs. Put eggs into the 3rd mixing bowl. Set aside. Fold the eggs until kneaded. Mix the 3rd mixing bowl well. Sift the flour until sifted.
+Take yeast from refrigerator. Put yeast into mixing bowl. Stir yeast into the mixing bowl. Add flour. Liquefy contents of the mixing bowl.
+Pour contents of the mixing bowl into the baking dish. Pour contents of the 3rd mixing bowl into the 2nd baking dish. Refrigerate for 2 hours.
+
+Egg Timer.
+
+Ingredients.
+eggs
+1 g salt
+
+Method.
+Clean mixing bowl. Fold eggs into the 2nd mixing bowl. Put eggs into mixing bowl. Boil the eggs. Put eggs into the 2nd mixing bowl. Remove salt from the 2nd mixing bowl. Serve with egg timer. Set aside. Cool the eggs until boiled.
+"#
+        .trim();
+        let kitchen = same(source, "66\n2\n", Limits::default()).unwrap();
+        assert_eq!(kitchen.mixing_bowls().len(), 3);
+        assert!(same(source, "", Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn test_errors_and_limits() {
+        let source = r#"
+Kitchen Sink.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with bottomless pot.
+
+Bottomless Pot.
+
+Ingredients.
+1 egg
+sugar
+
+Method.
+Beat the egg. Put egg into mixing bowl. Serve with bottomless pot. Whisk until beaten. Put sugar into mixing bowl.
+"#
+        .trim();
+        for limits in [
+            Limits {
+                max_steps: Some(9),
+                ..Limits::default()
+            },
+            Limits {
+                max_items: Some(10),
+                ..Limits::default()
+            },
+            Limits {
+                max_depth: Some(3),
+                ..Limits::default()
+            },
+        ] {
+            let error = same(source, "", limits).unwrap_err();
+            assert!(matches!(error.error, RuntimeError::LimitExceeded { .. }));
+        }
+
+        let source = source.replace("Serve with bottomless pot. Whisk", "Whisk the egg");
+        let error = same(&source, "", Limits::default()).unwrap_err();
+        assert!(matches!(
+            error.error,
+            RuntimeError::UninitializedIngredient { .. }
+        ));
+        assert_eq!(error.backtrace.len(), 2);
+    }
+
+    #[test]
+    fn test_item_limit_on_call() {
+        let source = r#"
+Copied Eggs.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Put egg into mixing bowl. Serve with echo.
+
+Echo.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with echo.
+"#
+        .trim();
+        let limits = Limits {
+            max_items: Some(5),
+            ..Limits::default()
+        };
+        // Sous-chefs that only call for another one are stopped by the eggs they hold
+        let error = same(source, "", limits).unwrap_err();
+        assert_eq!(error.to_string(), "Ingredient limit of 5 exceeded");
+        assert_eq!(error.backtrace.len(), 3);
+    }
+
+    #[test]
+    fn test_item_limit_on_return() {
+        let source = r#"
+Leftovers.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Put egg into mixing bowl. Pour contents of the mixing bowl into the baking dish. Serve with fridge.
+
+Fridge.
+
+Ingredients.
+1 egg
+
+Method.
+Refrigerate for 2 hours.
+"#
+        .trim();
+        let limits = Limits {
+            max_items: Some(5),
+            ..Limits::default()
+        };
+        // Nothing is served when the first bowl cannot be handed back
+        let error = same(source, "", limits).unwrap_err();
+        assert_eq!(error.to_string(), "Ingredient limit of 5 exceeded");
+        assert_eq!(error.backtrace.len(), 2);
+    }
+}
@@ -1,5 +1,6 @@
 use chumsky::span::SimpleSpan;
 
+pub mod debugger;
 pub mod interpreter;
 pub mod parser;
 pub mod validator;
@@ -1,100 +1,521 @@
-use std::path::PathBuf;
+use std::{
+    cell::RefCell, fs::File, io::BufWriter, path::PathBuf, rc::Rc, str::FromStr, time::Duration,
+};
+
+mod coverage;
+mod debug;
+mod profile;
+mod repl;
 
 use ariadne::{sources, Color, Label, Report, ReportKind};
 use spatula::{
-    parser::{parse, ParseError},
-    validator,
+    interpreter::{
+        compile, Coverage, EndOfInput, Input, Interpreter, InvalidCodepoints, Limits, NoShuffle,
+        Number, Overflow, Profile, Serving, Snapshot, StdIo, Trace, TracedError,
+    },
+    parser::{parse, ChefProgram, ParseError},
+    validator::{self, Diagnostic, Severity},
 };
 
-fn main() {
-    let path = std::env::args()
-        .nth(1)
-        .expect("Expected path to source file");
-    let filename = PathBuf::from(&path)
+const USAGE: &str = "Usage: spatula [run|debug] [options] <recipe>
+       spatula repl [options] [recipe]
+
+Commands:
+    run      Run the recipe, which is the default
+    debug    Step through the recipe interactively
+    repl     Type in ingredients and method steps one at a time, starting from a recipe if given
+
+Options:
+    --overflow checked|wrapping|saturating
+    --bigint
+    --seed <number>
+    --no-shuffle
+    --max-steps <number>
+    --max-items <number>
+    --max-depth <number>
+    --max-output <bytes>
+    --timeout <seconds>
+    --invalid-chars error|replace|number
+    --filter-control
+    --eof error|zero|stop
+    --chars
+    --trace <path>
+    --profile            Report how often each step ran and how long it took
+    --folded <path>      Profile, and write folded stacks for flamegraph tools here
+    --coverage <path>    Add the steps that ran to this lcov tracefile, and summarise it
+    --snapshot <path>    Save the kitchen here if the recipe stops early, such as at a limit
+    --resume <path>      Carry on from a snapshot of the same recipe
+    --vm                 Compile the recipe to bytecode and run that instead";
+
+/// Command line options.
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<String>,
+    overflow: Overflow,
+    bigint: bool,
+    seed: Option<u64>,
+    no_shuffle: bool,
+    limits: Limits,
+    serving: Serving,
+    input: Input,
+    trace: Option<String>,
+    profile: bool,
+    folded: Option<String>,
+    coverage: Option<String>,
+    snapshot: Option<String>,
+    resume: Option<String>,
+    vm: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--overflow" => {
+                    options.overflow = match args.next().as_deref() {
+                        Some("checked") => Overflow::Checked,
+                        Some("wrapping") => Overflow::Wrapping,
+                        Some("saturating") => Overflow::Saturating,
+                        _ => {
+                            return Err("Expected checked, wrapping or saturating after --overflow"
+                                .to_string())
+                        }
+                    }
+                }
+                "--seed" => options.seed = Some(value(&mut args, &arg)?),
+                "--max-steps" => options.limits.max_steps = Some(value(&mut args, &arg)?),
+                "--max-items" => options.limits.max_items = Some(value(&mut args, &arg)?),
+                "--max-depth" => options.limits.max_depth = Some(value(&mut args, &arg)?),
+                "--max-output" => options.limits.max_output_bytes = Some(value(&mut args, &arg)?),
+                "--timeout" => {
+                    let seconds = value(&mut args, &arg)?;
+                    let timeout = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("Invalid number of seconds {seconds}"))?;
+                    options.limits.timeout = Some(timeout);
+                }
+                "--invalid-chars" => {
+                    options.serving.invalid_codepoints = match args.next().as_deref() {
+                        Some("error") => InvalidCodepoints::Error,
+                        Some("replace") => InvalidCodepoints::Replace,
+                        Some("number") => InvalidCodepoints::Number,
+                        _ => {
+                            return Err("Expected error, replace or number after --invalid-chars"
+                                .to_string())
+                        }
+                    }
+                }
+                "--filter-control" => options.serving.filter_control = true,
+                "--eof" => {
+                    options.input.end_of_input = match args.next().as_deref() {
+                        Some("error") => EndOfInput::Error,
+                        Some("zero") => EndOfInput::Zero,
+                        Some("stop") => EndOfInput::Stop,
+                        _ => return Err("Expected error, zero or stop after --eof".to_string()),
+                    }
+                }
+                "--chars" => options.input.chars = true,
+                "--trace" => {
+                    let path = args.next().ok_or("Expected a path after --trace")?;
+                    options.trace = Some(path);
+                }
+                "--profile" => options.profile = true,
+                "--folded" => {
+                    let path = args.next().ok_or("Expected a path after --folded")?;
+                    options.folded = Some(path);
+                    options.profile = true;
+                }
+                "--coverage" => {
+                    let path = args.next().ok_or("Expected a path after --coverage")?;
+                    options.coverage = Some(path);
+                }
+                "--snapshot" => {
+                    let path = args.next().ok_or("Expected a path after --snapshot")?;
+                    options.snapshot = Some(path);
+                }
+                "--resume" => {
+                    let path = args.next().ok_or("Expected a path after --resume")?;
+                    options.resume = Some(path);
+                }
+                "--no-shuffle" => options.no_shuffle = true,
+                "--vm" => options.vm = true,
+                "--bigint" if cfg!(feature = "bigint") => options.bigint = true,
+                "--bigint" => {
+                    return Err("spatula was built without the bigint feature".to_string())
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("Unexpected argument {arg}")),
+            }
+        }
+        options.path = path;
+        Ok(options)
+    }
+}
+
+/// Parses the value following `flag`.
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    args.next()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Expected a number after {flag}"))
+}
+
+/// How many `Serve with` steps of a backtrace are labelled before the rest are summarised.
+const MAX_BACKTRACE_LABELS: usize = 8;
+
+fn interpreter(options: &Options) -> Interpreter {
+    let interpreter = Interpreter::new()
+        .with_overflow(options.overflow)
+        .with_limits(options.limits)
+        .with_serving(options.serving)
+        .with_input(options.input);
+    // `main` rejects `--seed` together with `--no-shuffle`
+    match (options.seed, options.no_shuffle) {
+        (Some(seed), _) => interpreter.with_seed(seed),
+        (None, true) => interpreter.with_rng(NoShuffle),
+        (None, false) => interpreter,
+    }
+}
+
+/// Has a [`Trace`] of every step written to the file given by `--trace`.
+fn with_trace<N: Number + 'static>(
+    interpreter: Interpreter<StdIo, N>,
+    contents: &str,
+    options: &Options,
+) -> Interpreter<StdIo, N> {
+    let Some(path) = &options.trace else {
+        return interpreter;
+    };
+    let file = File::create(path).unwrap_or_else(|e| {
+        eprintln!("Failed to create {path}: {e}");
+        std::process::exit(1);
+    });
+    interpreter.with_observer(Trace::new(BufWriter::new(file), contents))
+}
+
+/// Observers that are reported on once the recipe is done, as asked for by `--profile` and
+/// `--coverage`.
+struct Watchers {
+    profile: Option<Rc<RefCell<Profile>>>,
+    coverage: Option<Rc<RefCell<Coverage>>>,
+}
+
+/// Has every step traced and watched by `watchers`. The profile is added last, so that it times
+/// steps without the other observers.
+fn observed<N: Number + 'static>(
+    interpreter: Interpreter<StdIo, N>,
+    contents: &str,
+    options: &Options,
+    watchers: &Watchers,
+) -> Interpreter<StdIo, N> {
+    let mut interpreter = with_trace(interpreter, contents, options);
+    if let Some(coverage) = &watchers.coverage {
+        interpreter = interpreter.with_observer(Rc::clone(coverage));
+    }
+    if let Some(profile) = &watchers.profile {
+        interpreter = interpreter.with_observer(Rc::clone(profile));
+    }
+    interpreter
+}
+
+fn run(
+    program: &ChefProgram<'_>,
+    contents: &str,
+    options: &Options,
+    watchers: &Watchers,
+) -> Result<(), TracedError> {
+    let interpreter = interpreter(options);
+    #[cfg(feature = "bigint")]
+    if options.bigint {
+        let interpreter = interpreter.with_number::<num_bigint::BigInt>();
+        let interpreter = observed(interpreter, contents, options, watchers);
+        return prepare(interpreter, program, contents, options);
+    }
+    let interpreter = observed(interpreter, contents, options, watchers);
+    prepare(interpreter, program, contents, options)
+}
+
+/// Runs the main recipe, or carries on from the snapshot given by `--resume`. Saves a snapshot to
+/// the path given by `--snapshot` if the recipe fails. Runs compiled bytecode instead with `--vm`.
+fn prepare<N: Number>(
+    mut interpreter: Interpreter<StdIo, N>,
+    program: &ChefProgram<'_>,
+    contents: &str,
+    options: &Options,
+) -> Result<(), TracedError> {
+    if options.vm {
+        return interpreter.run_bytecode(&compile(program)).map(drop);
+    }
+    let result = match &options.resume {
+        Some(path) => {
+            let snapshot = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|snapshot| Snapshot::parse(&snapshot).map_err(|e| e.to_string()))
+                .and_then(|snapshot| {
+                    interpreter
+                        .resume(program, contents, &snapshot)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = snapshot {
+                eprintln!("Failed to resume from {path}: {e}");
+                std::process::exit(1);
+            }
+            interpreter.run_to_end(program)
+        }
+        None => interpreter.run_program(program),
+    };
+    // A failed step leaves the kitchen as it was before it, so resuming tries the step again
+    if let (Err(_), Some(path)) = (&result, &options.snapshot) {
+        if !interpreter.frames().is_empty() {
+            match std::fs::write(path, interpreter.snapshot(contents).to_string()) {
+                Ok(()) => eprintln!("Saved the kitchen to {path}, carry on with --resume {path}"),
+                Err(e) => eprintln!("Failed to write {path}: {e}"),
+            }
+        }
+    }
+    result.map(drop)
+}
+
+/// Reports a runtime error at the failing step, with every `Serve with` step that led to it.
+fn report_runtime_error(filename: &str, contents: &str, e: &TracedError) {
+    let filename = filename.to_string();
+    let recipe = &e.backtrace[0].title;
+    let mut report = Report::build(ReportKind::Error, filename.clone(), e.span().start)
+        .with_code(e.code())
+        .with_message(e.to_string())
+        .with_label(
+            Label::new((filename.clone(), e.span().into_range()))
+                .with_message(format!("{e} in `{recipe}`"))
+                .with_color(Color::Red)
+                .with_order(0),
+        );
+    // Each sous-chef was called for by the recipe after it, and a recursive recipe calls from the same step
+    let mut calls: Vec<(_, &str, &str, usize)> = Vec::new();
+    for (frame, caller) in e.backtrace.iter().zip(&e.backtrace[1..]) {
+        let span = frame.call_span.expect("Sous-chefs are called from a step");
+        match calls.last_mut() {
+            Some((last, .., times)) if *last == span => *times += 1,
+            _ => calls.push((span, &caller.title, &frame.title, 1)),
+        }
+    }
+    for (order, (span, caller, callee, times)) in
+        calls.iter().take(MAX_BACKTRACE_LABELS).enumerate()
+    {
+        let message = match times {
+            1 => format!("`{caller}` calls for `{callee}` here"),
+            _ => format!("`{caller}` calls for `{callee}` here, {times} times in a row"),
+        };
+        report = report.with_label(
+            Label::new((filename.clone(), span.into_range()))
+                .with_message(message)
+                .with_color(Color::Yellow)
+                .with_order(order as i32 + 1),
+        );
+    }
+    if calls.len() > MAX_BACKTRACE_LABELS {
+        let hidden = calls.len() - MAX_BACKTRACE_LABELS;
+        report = report.with_note(format!("{hidden} more calling steps are not shown"));
+    }
+    report
+        .finish()
+        .eprint(sources([(filename.clone(), contents)]))
+        .unwrap();
+}
+
+/// The name of the file at `path`, for reports.
+fn filename(path: &str) -> String {
+    PathBuf::from(path)
         .file_name()
         .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| path.clone());
-    let contents = std::fs::read_to_string(path).expect("Failed to read file");
-    let program = match parse(&contents) {
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Parses and validates a recipe, reporting everything wrong with it.
+fn parse_and_check<'a>(filename: &str, contents: &'a str) -> Option<ChefProgram<'a>> {
+    let program = match parse(contents) {
         Ok(ast) => ast,
         Err(error) => {
-            match error {
-                ParseError::FirstStage(errors) => {
-                    for e in errors {
-                        Report::build(ReportKind::Error, filename.clone(), e.span().start)
-                            .with_message(e.to_string())
-                            .with_label(
-                                Label::new((filename.clone(), e.span().into_range()))
-                                    .with_message(e.reason().to_string())
-                                    .with_color(Color::Red),
-                            )
-                            .finish()
-                            .eprint(sources([(filename.clone(), contents.clone())]))
-                            .unwrap();
-                    }
-                }
-                ParseError::SecondStage(error_msg, span) => {
-                    Report::build(ReportKind::Error, filename.clone(), span.start)
-                        .with_message(error_msg.clone())
-                        .with_label(
-                            Label::new((filename.clone(), span.into_range()))
-                                .with_message(error_msg)
-                                .with_color(Color::Red),
-                        )
-                        .finish()
-                        .eprint(sources([(filename.clone(), contents.clone())]))
-                        .unwrap();
-                }
+            report_parse_error(filename, contents, error);
+            return None;
+        }
+    };
+
+    let diagnostics = validator::validate(&program);
+    for diagnostic in &diagnostics {
+        report_diagnostic(filename, contents, diagnostic);
+    }
+    if validator::has_errors(&diagnostics) {
+        return None;
+    }
+    Some(program)
+}
+
+fn report_diagnostic(filename: &str, contents: &str, diagnostic: &Diagnostic) {
+    let filename = filename.to_string();
+    let (kind, color) = match diagnostic.severity {
+        Severity::Error => (ReportKind::Error, Color::Red),
+        Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        Severity::Note => (ReportKind::Advice, Color::Blue),
+    };
+    let mut report = Report::build(kind, filename.clone(), diagnostic.span.start)
+        .with_message(diagnostic.message.clone())
+        .with_label(
+            Label::new((filename.clone(), diagnostic.span.into_range()))
+                .with_message(diagnostic.message.clone())
+                .with_color(color)
+                .with_order(0),
+        );
+    for (order, (span, message)) in diagnostic.labels.iter().enumerate() {
+        report = report.with_label(
+            Label::new((filename.clone(), span.into_range()))
+                .with_message(message)
+                .with_color(Color::Cyan)
+                .with_order(order as i32 + 1),
+        );
+    }
+    if let Some(note) = &diagnostic.note {
+        report = report.with_note(note);
+    }
+    report
+        .finish()
+        .eprint(sources([(filename.clone(), contents)]))
+        .unwrap();
+}
+
+fn report_parse_error(filename: &str, contents: &str, error: ParseError) {
+    let filename = filename.to_string();
+    match error {
+        ParseError::FirstStage(errors) => {
+            for e in errors {
+                Report::build(ReportKind::Error, filename.clone(), e.span().start)
+                    .with_message(e.to_string())
+                    .with_label(
+                        Label::new((filename.clone(), e.span().into_range()))
+                            .with_message(e.reason().to_string())
+                            .with_color(Color::Red),
+                    )
+                    .finish()
+                    .eprint(sources([(filename.clone(), contents)]))
+                    .unwrap();
             }
-            std::process::exit(1);
         }
+        ParseError::SecondStage(error_msg, span) => {
+            Report::build(ReportKind::Error, filename.clone(), span.start)
+                .with_message(error_msg.clone())
+                .with_label(
+                    Label::new((filename.clone(), span.into_range()))
+                        .with_message(error_msg)
+                        .with_color(Color::Red),
+                )
+                .finish()
+                .eprint(sources([(filename.clone(), contents)]))
+                .unwrap();
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let command = args
+        .next_if(|arg| arg == "run" || arg == "debug" || arg == "repl")
+        .filter(|command| command != "run");
+    let options = Options::parse(args).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        std::process::exit(2);
+    });
+
+    if options.seed.is_some() && options.no_shuffle {
+        eprintln!("--seed and --no-shuffle cannot be used together");
+        std::process::exit(2);
+    }
+
+    if command.is_some() && (options.snapshot.is_some() || options.resume.is_some()) {
+        eprintln!("--snapshot and --resume are only supported when running a recipe");
+        std::process::exit(2);
+    }
+
+    if command.is_some() && (options.profile || options.coverage.is_some()) {
+        eprintln!("--profile, --folded and --coverage are only supported when running a recipe");
+        std::process::exit(2);
+    }
+    if options.vm {
+        let unsupported = [
+            command.is_some(),
+            options.trace.is_some(),
+            options.profile,
+            options.coverage.is_some(),
+            options.snapshot.is_some(),
+            options.resume.is_some(),
+        ];
+        if unsupported.contains(&true) {
+            eprintln!("--vm only runs a recipe, without --trace, --profile, --coverage, --snapshot or --resume");
+            std::process::exit(2);
+        }
+    }
+    if command.as_deref() == Some("repl") {
+        if options.trace.is_some() {
+            eprintln!("--trace is not supported by repl");
+            std::process::exit(2);
+        }
+        let interpreter = interpreter(&options);
+        let path = options.path.as_deref();
+        #[cfg(feature = "bigint")]
+        if options.bigint {
+            repl::session(interpreter.with_number::<num_bigint::BigInt>(), path);
+            return;
+        }
+        repl::session(interpreter, path);
+        return;
+    }
+
+    let Some(path) = options.path.clone() else {
+        eprintln!("Expected path to source file\n{USAGE}");
+        std::process::exit(2);
+    };
+    let filename = filename(&path);
+    let contents = std::fs::read_to_string(&path).expect("Failed to read file");
+    let Some(program) = parse_and_check(&filename, &contents) else {
+        std::process::exit(1);
     };
 
-    if let Err(e) = validator::validate(&program) {
-        Report::build(ReportKind::Error, filename.clone(), e.span.start)
-            .with_message(e.message.clone())
-            .with_label(
-                Label::new((filename.clone(), e.span.into_range()))
-                    .with_message(e.message)
-                    .with_color(Color::Red),
-            )
-            .finish()
-            .eprint(sources([(filename.clone(), contents.clone())]))
-            .unwrap();
+    if command.as_deref() == Some("debug") {
+        let interpreter = interpreter(&options);
+        #[cfg(feature = "bigint")]
+        if options.bigint {
+            let interpreter = interpreter.with_number::<num_bigint::BigInt>();
+            let interpreter = with_trace(interpreter, &contents, &options);
+            debug::session(&program, &filename, &contents, interpreter);
+            return;
+        }
+        let interpreter = with_trace(interpreter, &contents, &options);
+        debug::session(&program, &filename, &contents, interpreter);
+        return;
+    }
+
+    let watchers = Watchers {
+        profile: options
+            .profile
+            .then(|| Rc::new(RefCell::new(Profile::new(&contents)))),
+        coverage: options
+            .coverage
+            .is_some()
+            .then(|| Rc::new(RefCell::new(Coverage::new(&program, &contents)))),
+    };
+    let result = run(&program, &contents, &options, &watchers);
+    if let Some(profile) = &watchers.profile {
+        profile::report(
+            &filename,
+            &contents,
+            &profile.borrow(),
+            options.folded.as_deref(),
+        );
+    }
+    if let (Some(coverage), Some(tracefile)) = (&watchers.coverage, &options.coverage) {
+        coverage::report(&path, &contents, &mut coverage.borrow_mut(), tracefile);
+    }
+    if let Err(e) = result {
+        report_runtime_error(&filename, &contents, &e);
         std::process::exit(1);
     }
-    println!("{:#?}", program);
-
-    // for recipe in [ast.main].into_iter().chain(ast.auxilary.into_values()) {
-    //     for Spanned(instruction, span) in recipe.instructions {
-    //         let Instruction::VerbLoop(vl) = instruction else {
-    //             continue;
-    //         };
-
-    //         Report::build(ReportKind::Advice, filename.clone(), span.start)
-    //             .with_message("Look at this loop bruv")
-    //             .with_label(
-    //                 Label::new((filename.clone(), span.into_range()))
-    //                     .with_message("Here is the loop bruv")
-    //                     .with_color(Color::Red),
-    //             )
-    //             .finish()
-    //             .eprint(sources([(filename.clone(), contents.clone())]))
-    //             .unwrap();
-    //     }
-    // }
-
-    // if let Err(e) = interpreter::Interpreter::new().run_program(ast) {
-    //     Report::build(ReportKind::Error, filename.clone(), e.span().start)
-    //         .with_message(e.to_string())
-    //         .with_label(
-    //             Label::new((filename.clone(), e.span().into_range()))
-    //                 .with_message(e.reason().to_string())
-    //                 .with_color(Color::Red),
-    //         )
-    //         .finish()
-    //         .print(sources([(filename.clone(), contents.clone())]))
-    //         .unwrap();
-    //     std::process::exit(1);
-    // }
 }
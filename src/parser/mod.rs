@@ -9,7 +9,66 @@ pub use ast::*;
 pub use errors::ParseError;
 pub use stage_two_ast::{ChefProgram, Ingredient, IngredientKind, Instruction, VerbLoop};
 
-pub fn parse<'a>(input: &'a str) -> Result<ChefProgram<'a>, ParseError> {
+pub fn parse<'a>(input: &'a str) -> Result<ChefProgram<'a>, ParseError<'a>> {
     let initial_ast = stage_one::parse(input)?;
     stage_two::parse(initial_ast)
 }
+
+/// Parses method steps on their own, such as `Put eggs into mixing bowl. Stir for 2 minutes.`
+pub fn parse_steps<'a>(input: &'a str) -> Result<Vec<Spanned<Instruction<'a>>>, ParseError<'a>> {
+    stage_two::parse_instructions(stage_one::parse_steps(input)?)
+}
+
+/// Whether method steps open more loops than they close, so that they need more steps before
+/// they parse.
+pub fn opens_loop(input: &str) -> bool {
+    let Ok(steps) = stage_one::parse_steps(input) else {
+        return false;
+    };
+    let count = |opens: bool| {
+        steps
+            .iter()
+            .filter(|Spanned(step, _)| match step {
+                stage_one_ast::CookingInstruction::Verb(..) => opens,
+                stage_one_ast::CookingInstruction::VerbUntil(..) => !opens,
+                _ => false,
+            })
+            .count()
+    };
+    count(true) > count(false)
+}
+
+/// Parses a line of an ingredient list such as `2 cups flour`, ending with a line break.
+pub fn parse_ingredient<'a>(input: &'a str) -> Result<Spanned<Ingredient<'a>>, ParseError<'a>> {
+    let ingredient = stage_one::parse_ingredient(input)?;
+    let mut ingredients = stage_two::parse_ingredients(vec![ingredient])?;
+    Ok(ingredients.remove(0))
+}
+
+/// Parses a recipe written inline in a test, panicking with the reason if it does not parse.
+#[cfg(test)]
+pub(crate) fn parse_recipe(source: &str) -> ChefProgram<'_> {
+    match parse(source) {
+        Ok(program) => program,
+        Err(ParseError::FirstStage(errors)) => {
+            let reasons: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+            panic!("Failed to parse recipe: {}", reasons.join(", "))
+        }
+        Err(ParseError::SecondStage(reason, _)) => panic!("Failed to parse recipe: {reason}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_loop() {
+        assert!(opens_loop("Sift the brown sugar.\n"));
+        assert!(opens_loop("Sift the flour. Put flour into mixing bowl.\n"));
+        assert!(opens_loop("Sift the flour. Beat the egg. Beat until beaten.\n"));
+        assert!(!opens_loop("Sift the flour. Sift until sifted.\n"));
+        assert!(!opens_loop("Put brown sugar into the 2nd mixing bowl.\n"));
+        assert!(!opens_loop("Put flour into mixing bowl and stir it.\n"));
+    }
+}
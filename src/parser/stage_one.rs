@@ -11,6 +11,16 @@ fn double_line_break<'a>() -> impl Parser<'a, &'a str, &'a str, extra::Err<Rich<
     line_break().then(line_break()).to_slice()
 }
 
+/// A whole number. Numbers too large for a `usize` are reported as an error.
+fn number<'a>() -> impl Parser<'a, &'a str, usize, extra::Err<Rich<'a, char>>> {
+    text::int(10).validate(|s: &str, e, emitter| {
+        s.parse().unwrap_or_else(|_| {
+            emitter.emit(Rich::custom(e.span(), format!("Number `{s}` is too large")));
+            usize::MAX
+        })
+    })
+}
+
 fn measure_unit<'a>() -> impl Parser<'a, &'a str, MeasureUnit, extra::Err<Rich<'a, char>>> {
     just("g")
         .map(|_| MeasureUnit::Grams)
@@ -40,7 +50,7 @@ fn measure_type<'a>() -> impl Parser<'a, &'a str, MeasureType, extra::Err<Rich<'
 fn ingredient<'a>() -> impl Parser<'a, &'a str, Spanned<CookingIngredient<'a>>, extra::Err<Rich<'a, char>>>
 {
     // [initial-value] [[measure-type] measure] ingredient-name
-    let initial_value = text::int(10).map(|s: &str| s.parse().unwrap());
+    let initial_value = number();
     let ingredient_name = any()
         .and_is(line_break().not())
         .repeated()
@@ -67,15 +77,14 @@ fn ingredient<'a>() -> impl Parser<'a, &'a str, Spanned<CookingIngredient<'a>>,
 }
 
 fn nth<'a>() -> impl Parser<'a, &'a str, usize, extra::Err<Rich<'a, char>>> {
-    text::int(10)
-        .map(|s: &str| s.parse().unwrap())
+    number()
         .then_ignore(just("th").or(just("st")).or(just("nd")).or(just("rd")))
         .padded()
 }
 
 fn serves_instruction<'a>() -> impl Parser<'a, &'a str, usize, extra::Err<Rich<'a, char>>> {
     just("Serves ")
-        .ignore_then(text::int(10).map(|s: &str| s.parse().unwrap()))
+        .ignore_then(number())
 }
 
 fn instruction<'a>(
@@ -227,7 +236,7 @@ fn instruction<'a>(
                 .ignore_then(nth().or_not())
                 .then_ignore(just("mixing bowl ").or_not())
                 .then_ignore(just("for "))
-                .then(text::int(10).map(|s: &str| s.parse().unwrap()))
+                .then(number())
                 .then_ignore(just(" minute"))
                 .then_ignore(just("s").or_not())
                 .map(|(bowl, minutes)| CookingInstruction::Stir(bowl.unwrap_or(0), minutes)),
@@ -291,7 +300,7 @@ fn instruction<'a>(
             just("Refrigerate")
                 .ignore_then(
                     just(" for ")
-                    .ignore_then(text::int(10).map(|s: &str| s.parse().unwrap()))
+                    .ignore_then(number())
                     .then_ignore(just(" hours"))
                     .or_not()
                 )
@@ -326,7 +335,7 @@ fn instruction<'a>(
         .map_with(Spanned::from_with_extra)
 }
 
-fn parser<'a>() -> impl Parser<'a, &'a str, Vec<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>, extra::Err<Rich<'a, char>>> {
+fn parser<'a>() -> impl Parser<'a, &'a str, Vec<Spanned<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>>, extra::Err<Rich<'a, char>>> {
     let title = any().and_is(line_break().not()).and_is(just('.').not()).repeated().to_slice().then_ignore(just(".").or_not());
     let ingredients_header = || just("Ingredients.").then(line_break());
 
@@ -346,9 +355,8 @@ fn parser<'a>() -> impl Parser<'a, &'a str, Vec<ChefRecipe<'a, CookingInstructio
         .separated_by(just(".").then(just(" ").or(line_break())))
         .collect();
 
-    let serves = double_line_break().ignore_then(serves_instruction())
-        .then_ignore(just("."))
-        .map_with(Spanned::from_with_extra)
+    let serves = double_line_break()
+        .ignore_then(serves_instruction().then_ignore(just(".")).map_with(Spanned::from_with_extra))
         .or_not();
 
     title
@@ -373,15 +381,33 @@ fn parser<'a>() -> impl Parser<'a, &'a str, Vec<ChefRecipe<'a, CookingInstructio
                 oven_temperature: None, // TODO
             },
         )
+        .map_with(Spanned::from_with_extra)
         .separated_by(double_line_break())
         .collect()
         .padded()
 }
 
-pub fn parse<'a>(input: &'a str) -> Result<Vec<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>, ParseError> {
+pub fn parse<'a>(input: &'a str) -> Result<Vec<Spanned<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>>, ParseError<'a>> {
     parser().parse(input).into_result().map_err(ParseError::FirstStage)
 }
 
+/// Method steps on their own, as typed into a REPL.
+pub fn parse_steps<'a>(input: &'a str) -> Result<Vec<Spanned<CookingInstruction<'a>>>, ParseError<'a>> {
+    instruction()
+        .separated_by(just(".").then(just(" ").or(line_break())))
+        .collect()
+        .then_ignore(just(".").or_not())
+        .padded()
+        .parse(input)
+        .into_result()
+        .map_err(ParseError::FirstStage)
+}
+
+/// A line of an ingredient list, ending with a line break.
+pub fn parse_ingredient<'a>(input: &'a str) -> Result<Spanned<CookingIngredient<'a>>, ParseError<'a>> {
+    ingredient().parse(input).into_result().map_err(ParseError::FirstStage)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -798,11 +824,40 @@ Eat moose. Serves 1.
 Serves 1.
 "#.trim();
         let recipe = parse!(parser(), input);
-        let recipe = recipe.first().unwrap();
+        let recipe = recipe.first().unwrap().value();
         let instructions = recipe.instructions.iter().map(Spanned::value).collect::<Vec<_>>();
         assert_eq!(instructions, vec![
             &CookingInstruction::Verb(Verb("Eat"), "moose"),
             &CookingInstruction::Serves(1)
         ]);
     }
+
+    #[test]
+    fn test_huge_number_is_an_error() {
+        let input = "Stir for 99999999999999999999999 minutes";
+        let errors = instruction().parse(input).into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Number `99999999999999999999999` is too large");
+        assert_eq!(errors[0].span().into_range(), 9..32);
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let Ok(steps) = parse_steps("Put eggs into mixing bowl. Beat the eggs. Stir for 2 minutes. Whisk until beaten.\n") else {
+            panic!("Failed to parse steps");
+        };
+        let steps = steps.iter().map(Spanned::value).collect::<Vec<_>>();
+        assert_eq!(steps, vec![
+            &CookingInstruction::Put("eggs", 0),
+            &CookingInstruction::Verb(Verb("Beat"), "eggs"),
+            &CookingInstruction::Stir(0, 2),
+            &CookingInstruction::VerbUntil(None, Verb("beaten")),
+        ]);
+
+        let Ok(ingredient) = parse_ingredient("2 cups flour\n") else {
+            panic!("Failed to parse ingredient");
+        };
+        assert_eq!(ingredient.value().name, "flour");
+        assert_eq!(ingredient.value().initial_value, Some(2));
+    }
 }
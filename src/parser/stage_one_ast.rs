@@ -1,7 +1,7 @@
 use super::ast::Verb;
 
 /**
- * Introduction
+Introduction
 Chef is a programming language in which programs look like recipes.
 
 NEW: Additional syntax specifications added 17 July, 2003, marked in red. Fixed spelling of "liquefy" keyword.
@@ -6,13 +6,17 @@ use crate::parser::stage_two_ast::IngredientKind;
 
 use super::{
     stage_one_ast::{CookingIngredient, CookingInstruction},
-    ChefProgram, ChefRecipe, Ingredient, Instruction, ParseError, Spanned, VerbLoop,
+    ChefProgram, ChefRecipe, Ingredient, Instruction, ParseError, Spanned, Verb, VerbLoop,
 };
 
 pub fn parse<'a>(
-    input: Vec<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>,
-) -> Result<ChefProgram<'a>, ParseError> {
-    let mut functions = input.into_iter();
+    input: Vec<Spanned<ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>>>,
+) -> Result<ChefProgram<'a>, ParseError<'a>> {
+    let recipes = input
+        .iter()
+        .map(|Spanned(recipe, span)| Spanned(recipe.title, *span))
+        .collect();
+    let mut functions = input.into_iter().map(Spanned::into_value);
     let Some(main) = functions.next() else {
         return Err(ParseError::SecondStage(
             "No main recipe found".to_string(),
@@ -20,16 +24,26 @@ pub fn parse<'a>(
         ));
     };
     let main = parse_recipe(main)?;
-    let auxilary = functions
-        .map(parse_recipe)
-        .map(|recipe| recipe.map(|recipe| (recipe.title.to_lowercase(), recipe)))
-        .collect::<Result<HashMap<String, _>, ParseError>>()?;
-    Ok(ChefProgram { main, auxilary })
+    // A later recipe with the same title replaces an earlier one, which the validator reports
+    let mut auxilary = HashMap::new();
+    let mut shadowed = vec![];
+    for recipe in functions {
+        let recipe = parse_recipe(recipe)?;
+        if let Some(earlier) = auxilary.insert(recipe.title.to_lowercase(), recipe) {
+            shadowed.push(earlier);
+        }
+    }
+    Ok(ChefProgram {
+        main,
+        auxilary,
+        recipes,
+        shadowed,
+    })
 }
 
 fn parse_recipe<'a>(
     recipe: ChefRecipe<'a, CookingInstruction<'a>, CookingIngredient<'a>>,
-) -> Result<ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>, ParseError> {
+) -> Result<ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>, ParseError<'a>> {
     let ChefRecipe {
         title,
         comments,
@@ -54,9 +68,9 @@ fn parse_recipe<'a>(
     })
 }
 
-fn parse_ingredients<'a>(
+pub(super) fn parse_ingredients<'a>(
     ingredients: Vec<Spanned<CookingIngredient<'a>>>,
-) -> Result<Vec<Spanned<Ingredient<'a>>>, ParseError> {
+) -> Result<Vec<Spanned<Ingredient<'a>>>, ParseError<'a>> {
     ingredients
         .into_iter()
         .map(|Spanned(ingredient, span)| {
@@ -79,20 +93,29 @@ fn parse_ingredients<'a>(
         .collect::<Result<Vec<_>, ParseError>>()
 }
 
-fn parse_instructions<'a>(
+/// Opening verb, ingredient and steps so far of a loop waiting for its `until`, and the span of
+/// everything in it.
+type OpenLoop<'a> = (
+    Spanned<Verb<'a>>,
+    &'a str,
+    Vec<Spanned<Instruction<'a>>>,
+    SimpleSpan,
+);
+
+pub(super) fn parse_instructions<'a>(
     instructions: Vec<Spanned<CookingInstruction<'a>>>,
-) -> Result<Vec<Spanned<Instruction<'a>>>, ParseError> {
+) -> Result<Vec<Spanned<Instruction<'a>>>, ParseError<'a>> {
     let mut instructions_iter = instructions.into_iter();
-    let mut loop_stack: Vec<(VerbLoop, SimpleSpan)> = vec![];
+    let mut loop_stack: Vec<OpenLoop> = vec![];
     let mut instructions = vec![];
 
     loop {
         let Some(instruction) = instructions_iter.next() else {
-            if let Some((current_loop, loop_span)) = loop_stack.last() {
+            if let Some((Spanned(verb, _), .., loop_span)) = loop_stack.last() {
                 return Err(ParseError::SecondStage(
                     format!(
                         "Recipe ends during `{}` - matching `until` not found",
-                        current_loop.verb.0
+                        verb.0
                     ),
                     *loop_span,
                 ));
@@ -148,18 +171,12 @@ fn parse_instructions<'a>(
             CookingInstruction::Refrigerate(hours) => spanned!(Instruction::Refrigerate(hours)),
             CookingInstruction::Serves(serves) => spanned!(Instruction::Serves(serves)),
             CookingInstruction::Verb(verb, ingredient) => {
-                loop_stack.push((
-                    VerbLoop {
-                        verb: verb.clone(),
-                        ingredient,
-                        instructions: vec![],
-                    },
-                    span,
-                ));
+                loop_stack.push((Spanned::new(verb, span), ingredient, vec![], span));
                 continue;
             }
-            CookingInstruction::VerbUntil(_, verb) => {
-                let Some((current_loop, mut loop_span)) = loop_stack.pop() else {
+            CookingInstruction::VerbUntil(until_ingredient, verb) => {
+                let Some((opening, ingredient, instructions, mut loop_span)) = loop_stack.pop()
+                else {
                     return Err(ParseError::SecondStage(
                         format!("`until` {} with no matching initial {}", verb.0, verb.0),
                         span,
@@ -167,13 +184,20 @@ fn parse_instructions<'a>(
                 };
 
                 loop_span.end = span.end;
-                Spanned::new(Instruction::VerbLoop(current_loop), loop_span)
+                let verb_loop = VerbLoop {
+                    verb: opening,
+                    ingredient,
+                    instructions,
+                    until_ingredient,
+                    until: Spanned::new(verb, span),
+                };
+                Spanned::new(Instruction::VerbLoop(verb_loop), loop_span)
             }
         };
         if let Some(active_loop) = loop_stack.last_mut() {
-            let (verb_loop, span) = active_loop;
+            let (.., instructions, span) = active_loop;
             span.end = instruction.1.end;
-            verb_loop.instructions.push(instruction);
+            instructions.push(instruction);
         } else {
             instructions.push(instruction);
         }
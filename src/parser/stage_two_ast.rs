@@ -14,6 +14,12 @@ use super::{
 pub struct ChefProgram<'a> {
     pub main: ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>,
     pub auxilary: HashMap<String, ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>>,
+    /// Title and span of every recipe in the order they are written, the main recipe first. Unlike
+    /// `auxilary`, this keeps recipes whose title was already taken.
+    pub recipes: Vec<Spanned<&'a str>>,
+    /// Auxiliary recipes replaced in `auxilary` by a later one with the same title, kept so they
+    /// can still be checked.
+    pub shadowed: Vec<ChefRecipe<'a, Instruction<'a>, Ingredient<'a>>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -81,9 +87,15 @@ pub enum Instruction<'a> {
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct VerbLoop<'a> {
-    pub verb: Verb<'a>,
+    /// Verb opening the loop, with the span of its step.
+    pub verb: Spanned<Verb<'a>>,
     pub ingredient: &'a str,
     pub instructions: Vec<Spanned<Instruction<'a>>>,
+    /// Ingredient named in the closing `until` statement, decremented by 1 each time it is reached.
+    pub until_ingredient: Option<&'a str>,
+    /// Past tense verb of the closing `until` statement, such as `sifted` in `Shake until sifted`,
+    /// with the span of its step.
+    pub until: Spanned<Verb<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -97,6 +109,7 @@ pub struct Ingredient<'a> {
 /// * g | kg | pinch[es] : These always indicate dry measures.
 /// * ml | l | dash[es] : These always indicate liquid measures.
 /// * cup[s] | teaspoon[s] | tablespoon[s] : These indicate measures which may be either dry or liquid.
+///
 /// The optional measure-type may be any of the following:
 /// * heaped | level : These indicate that the measure is dry.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Copy)]
@@ -0,0 +1,97 @@
+//! Reports for `spatula run --profile`.
+
+use std::{fs::File, io::BufWriter, time::Duration};
+
+use ariadne::{sources, Color, Label, Report, ReportKind};
+use spatula::interpreter::{Profile, ProfiledRecipe};
+
+/// How many of the hottest steps are labelled in the source listing.
+const MAX_HOT_STEPS: usize = 10;
+
+/// Reports the hottest steps and the time spent in each recipe, and writes the folded stacks to
+/// `folded` if given.
+pub fn report(filename: &str, contents: &str, profile: &Profile, folded: Option<&str>) {
+    let steps = profile.hot_steps();
+    // A recipe failing at its first step has been entered but has no steps
+    let (Some(main), Some(hottest)) = (profile.main(), steps.first()) else {
+        eprintln!("No steps were taken, so there is nothing to profile");
+        return;
+    };
+    let total = main.total_time();
+
+    let filename = filename.to_string();
+    let runs: u64 = steps.iter().map(|step| step.runs).sum();
+    let mut report = Report::build(
+        ReportKind::Custom("Profile", Color::Cyan),
+        filename.clone(),
+        hottest.span.start,
+    )
+    .with_message(format!("{runs} steps took {total:?}"));
+    for (order, step) in steps.iter().take(MAX_HOT_STEPS).enumerate() {
+        let color = match order {
+            0 => Color::Red,
+            _ => Color::Yellow,
+        };
+        let runs = match step.runs {
+            1 => "1 run".to_string(),
+            runs => format!("{runs} runs"),
+        };
+        report = report.with_label(
+            Label::new((filename.clone(), step.span.into_range()))
+                .with_message(format!(
+                    "{runs}, {:?} ({:.1}%)",
+                    step.time,
+                    percent(step.time, total)
+                ))
+                .with_color(color)
+                .with_order(order as i32),
+        );
+    }
+    if steps.len() > MAX_HOT_STEPS {
+        let hidden = steps.len() - MAX_HOT_STEPS;
+        report = report.with_note(format!("{hidden} cooler steps are not shown"));
+    }
+    report
+        .finish()
+        .eprint(sources([(filename.clone(), contents)]))
+        .unwrap();
+
+    eprintln!("Time by recipe, with the recipes each one called for under it:");
+    print_recipe(&main, total, 1);
+
+    if let Some(path) = folded {
+        let written =
+            File::create(path).and_then(|file| profile.write_folded(BufWriter::new(file)));
+        match written {
+            Ok(()) => eprintln!("Wrote folded stacks to {path}"),
+            Err(e) => eprintln!("Failed to write {path}: {e}"),
+        }
+    }
+}
+
+fn print_recipe(recipe: &ProfiledRecipe, total: Duration, depth: usize) {
+    let calls = match recipe.calls {
+        1 => "1 time".to_string(),
+        calls => format!("{calls} times"),
+    };
+    eprintln!(
+        "{:indent$}{}: prepared {calls}, {} steps taking {:?}, {:?} ({:.1}%) with sous-chefs",
+        "",
+        recipe.title,
+        recipe.runs(),
+        recipe.self_time(),
+        recipe.total_time(),
+        percent(recipe.total_time(), total),
+        indent = depth * 4,
+    );
+    for callee in &recipe.callees {
+        print_recipe(callee, total, depth + 1);
+    }
+}
+
+fn percent(time: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        return 0.0;
+    }
+    time.as_secs_f64() / total.as_secs_f64() * 100.0
+}
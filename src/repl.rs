@@ -0,0 +1,316 @@
+//! The `spatula repl` command line.
+
+use std::{collections::HashMap, io::Write};
+
+use ariadne::{sources, Color, Label, Report, ReportKind};
+use spatula::{
+    interpreter::{Interpreter, Number, Position, Status, StdIo, TracedError},
+    parser::{
+        opens_loop, parse, parse_ingredient, parse_steps, ChefProgram, ChefRecipe, Instruction,
+        Spanned,
+    },
+};
+
+use crate::debug::{print_ingredients, print_kitchen};
+
+const HELP: &str = "Type ingredients like `2 cups flour`, then `Method.`, then method steps like
+`Put flour into mixing bowl.` Steps run as soon as they are entered, and a loop
+runs once its `until` step is entered.
+
+Commands:
+    :ingredients [name]    Print ingredients, or one ingredient
+    :bowls                 Print mixing bowls
+    :dishes                Print baking dishes
+    :reset                 Start again with no ingredients
+    :load <recipe>         Start again from a recipe file, running its method
+    :help                  Show this help
+    :quit                  Stop";
+
+/// Where an error happened, for reporting it.
+const INPUT: &str = "<input>";
+
+/// A recipe written one line at a time, and the chef preparing it.
+struct Session<N> {
+    interpreter: Interpreter<StdIo, N>,
+    recipe: Draft,
+    /// Set once `Method.` is entered and the ingredients are measured out
+    cooking: bool,
+    /// Steps waiting for the `until` of a loop
+    pending: String,
+}
+
+/// The text of the recipe so far. Parsed recipes borrow their text, so it is parsed again for
+/// every line rather than kept parsed, and is dropped with the recipe.
+#[derive(Default)]
+struct Draft {
+    /// A loaded recipe file, which auxiliary recipes come from
+    file: Option<(String, String)>,
+    /// How many steps of the loaded recipe's method are kept
+    file_steps: usize,
+    /// Lines of ingredients, each of which has parsed
+    ingredients: Vec<String>,
+    /// Steps entered, each entry of which has parsed
+    steps: Vec<String>,
+}
+
+/// Runs a REPL until the user quits, reading from STDIN. Starts from the recipe at `path` if given.
+pub fn session<N: Number>(interpreter: Interpreter<StdIo, N>, path: Option<&str>) {
+    let mut session = Session {
+        interpreter,
+        recipe: Draft::default(),
+        cooking: false,
+        pending: String::new(),
+    };
+    eprintln!("Type `:help` for help");
+    if let Some(path) = path {
+        session.load(path);
+    }
+
+    loop {
+        match (session.cooking, session.pending.is_empty()) {
+            (false, _) => eprint!("ingredient> "),
+            (true, true) => eprint!("step> "),
+            (true, false) => eprint!("...> "),
+        }
+        std::io::stderr().flush().ok();
+        // STDIN is only locked while a line is read, as `Take` reads from it too
+        let mut line = String::new();
+        if !matches!(std::io::stdin().read_line(&mut line), Ok(1..)) {
+            return;
+        }
+        line.truncate(line.trim_end_matches(['\n', '\r']).len());
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+        match command {
+            "" => {}
+            ":ingredients" | ":bowls" | ":dishes" if !session.cooking => {
+                eprintln!("Nothing is measured out before `Method.`")
+            }
+            ":ingredients" => print_ingredients(&session.interpreter.frames()[0], argument),
+            ":bowls" | ":dishes" => {
+                let kitchen = session.interpreter.frames()[0].kitchen();
+                print_kitchen(kitchen, command == ":bowls");
+            }
+            ":reset" => session.reset(),
+            ":load" if argument.is_empty() => eprintln!("Expected a path after :load"),
+            ":load" => session.load(argument),
+            ":help" => eprintln!("{HELP}"),
+            ":quit" => return,
+            _ if command.starts_with(':') => {
+                eprintln!("Unknown command `{command}`, type `:help` for help")
+            }
+            "Method." if !session.cooking => session.cook(),
+            _ if !session.cooking => session.declare(line),
+            _ => session.run(line),
+        }
+    }
+}
+
+impl<N: Number> Session<N> {
+    fn reset(&mut self) {
+        self.recipe = Draft::default();
+        self.cooking = false;
+        self.pending.clear();
+    }
+
+    fn load(&mut self, path: &str) {
+        let filename = crate::filename(path);
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Failed to read {path}: {e}");
+                return;
+            }
+        };
+        let Some(program) = crate::parse_and_check(&filename, &contents) else {
+            return;
+        };
+        let file_steps = program.main.instructions.len() + program.main.serves.iter().count();
+        self.reset();
+        self.recipe.file = Some((filename, contents));
+        self.recipe.file_steps = file_steps;
+        self.cook();
+    }
+
+    /// Measures out the ingredients and runs any steps there are already.
+    fn cook(&mut self) {
+        let program = self.recipe.program();
+        if let Err(error) = self.interpreter.start(&program) {
+            let error = self.interpreter.traced(error);
+            report(&error, self.recipe.file_source().unwrap_or(("", "")));
+            return;
+        }
+        self.cooking = true;
+        if let Err(error) = self.prepare() {
+            let source = self
+                .recipe
+                .file_source()
+                .expect("Only loaded recipes have steps");
+            report(&error, source);
+            // Keep the kitchen as it was when the recipe failed, and carry on after it
+            self.interpreter
+                .restore(self.interpreter.frames()[..1].to_vec());
+            self.skip_rest();
+        }
+    }
+
+    fn declare(&mut self, mut line: String) {
+        line.push('\n');
+        if let Err(error) = parse_ingredient(&line) {
+            crate::report_parse_error(INPUT, &line, error);
+            return;
+        }
+        self.recipe.ingredients.push(line);
+    }
+
+    /// Runs the steps on `line`, or waits for more lines until every loop on them is closed.
+    fn run(&mut self, line: String) {
+        let before = self.pending.len();
+        self.pending.push_str(&line);
+        self.pending.push('\n');
+        if let Err(error) = parse_steps(&self.pending) {
+            if !opens_loop(&self.pending) {
+                crate::report_parse_error(INPUT, &self.pending, error);
+                // Steps before the line are still waiting for their `until`
+                self.pending.truncate(before);
+            }
+            return;
+        }
+
+        let saved = self.interpreter.frames().to_vec();
+        self.recipe.steps.push(std::mem::take(&mut self.pending));
+        if let Err(error) = self.prepare() {
+            let source = match error.backtrace[0].call_span {
+                // Failed in an auxiliary recipe, which only loaded recipes have
+                Some(_) => self
+                    .recipe
+                    .file_source()
+                    .expect("Only loaded recipes have sous-chefs"),
+                None => (
+                    INPUT,
+                    self.recipe
+                        .steps
+                        .last()
+                        .expect("Steps were entered")
+                        .as_str(),
+                ),
+            };
+            report(&error, source);
+            self.recipe.steps.pop();
+            self.interpreter.restore(saved);
+        }
+    }
+
+    /// Steps until the head chef runs out of steps.
+    fn prepare(&mut self) -> Result<(), TracedError> {
+        let program = self.recipe.program();
+        loop {
+            let frames = self.interpreter.frames();
+            if let [main] = frames {
+                if let Position::EndOfRecipe(_) = main.position(&program) {
+                    return Ok(());
+                }
+            }
+            match self.interpreter.step(&program) {
+                Ok(Status::Running) => {}
+                Ok(Status::Finished(_)) => break,
+                Err(error) => return Err(self.interpreter.traced(error)),
+            }
+        }
+        eprintln!("The recipe has ended, so the kitchen is cleaned up");
+        self.recipe.file_steps = 0;
+        self.recipe.steps.clear();
+        self.cook();
+        Ok(())
+    }
+
+    /// Drops the steps the head chef has not got to, after a loaded recipe failed.
+    fn skip_rest(&mut self) {
+        let program = self.recipe.program();
+        let main = &self.interpreter.frames()[0];
+        let Some(span) = main.span(&program) else {
+            return;
+        };
+        if let Some(at) = program
+            .main
+            .instructions
+            .iter()
+            .position(|Spanned(_, s)| s.start >= span.start)
+        {
+            self.recipe.file_steps = at;
+        }
+    }
+}
+
+impl Draft {
+    /// Parses the recipe so far, with the steps of a loaded recipe before those entered.
+    fn program(&self) -> ChefProgram<'_> {
+        let mut program = match &self.file {
+            Some((_, contents)) => {
+                let Ok(mut program) = parse(contents) else {
+                    unreachable!("Loaded recipes have parsed before");
+                };
+                // Dishes are served as a step, since the recipe never ends by itself
+                if let Some(Spanned(diners, span)) = program.main.serves.take() {
+                    let serves = Spanned(Instruction::Serves(diners), span);
+                    program.main.instructions.push(serves);
+                }
+                program.main.instructions.truncate(self.file_steps);
+                program
+            }
+            None => scratch_pad(),
+        };
+        let main = &mut program.main;
+        main.ingredients.extend(
+            self.ingredients
+                .iter()
+                .filter_map(|line| parse_ingredient(line).ok()),
+        );
+        for steps in &self.steps {
+            main.instructions
+                .extend(parse_steps(steps).into_iter().flatten());
+        }
+        program
+    }
+
+    fn file_source(&self) -> Option<(&str, &str)> {
+        self.file
+            .as_ref()
+            .map(|(filename, contents)| (filename.as_str(), contents.as_str()))
+    }
+}
+
+fn report(error: &TracedError, (filename, contents): (&str, &str)) {
+    let filename = filename.to_string();
+    Report::build(ReportKind::Error, filename.clone(), error.span().start)
+        .with_code(error.code())
+        .with_message(error.to_string())
+        .with_label(
+            Label::new((filename.clone(), error.span().into_range()))
+                .with_message(format!("{error} in `{}`", error.backtrace[0].title))
+                .with_color(Color::Red),
+        )
+        .finish()
+        .eprint(sources([(filename, contents)]))
+        .unwrap();
+}
+
+/// A main recipe with nothing in it yet.
+fn scratch_pad<'a>() -> ChefProgram<'a> {
+    ChefProgram {
+        main: ChefRecipe {
+            title: "Scratch Pad",
+            comments: "",
+            ingredients: vec![],
+            cooking_time: None,
+            oven_temperature: None,
+            instructions: vec![],
+            serves: None,
+        },
+        auxilary: HashMap::new(),
+        recipes: vec![],
+        shadowed: vec![],
+    }
+}
@@ -1,63 +1,372 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::{
-    parser::{ChefProgram, ChefRecipe, Ingredient, Instruction, Spanned, VerbLoop},
-    SpatulaError,
+use chumsky::span::SimpleSpan;
+
+mod verbs;
+
+use crate::parser::{
+    ChefProgram, ChefRecipe, Ingredient, IngredientKind, Instruction, Spanned, Verb, VerbLoop,
 };
 
-pub fn validate<'a>(program: &ChefProgram<'a>) -> Result<(), SpatulaError> {
-    validate_recipe_references(program)?;
-    for recipe in std::iter::once(&program.main).chain(program.auxilary.values()) {
-        validate_ingredient_references(recipe)?;
+/// How serious a [`Diagnostic`] is. Only errors stop a recipe from being run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Something wrong with, or worth knowing about, a recipe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: SimpleSpan,
+    /// Other places the diagnostic is about, with what to say at each.
+    pub labels: Vec<(SimpleSpan, String)>,
+    /// Explanation shown after the source.
+    pub note: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(message: S, span: SimpleSpan) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            labels: vec![],
+            note: None,
+        }
+    }
+
+    pub fn warning<S: Into<String>>(message: S, span: SimpleSpan) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn note<S: Into<String>>(message: S, span: SimpleSpan) -> Self {
+        Self {
+            severity: Severity::Note,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn with_label<S: Into<String>>(mut self, span: SimpleSpan, message: S) -> Self {
+        self.labels.push((span, message.into()));
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+/// Checks every recipe of a program, returning all diagnostics in the order they appear in the
+/// source.
+pub fn validate<'a>(program: &ChefProgram<'a>) -> Vec<Diagnostic> {
+    let mut diagnostics = validate_recipe_titles(program);
+    diagnostics.extend(validate_recipe_references(program));
+    // Recipes shadowed by a later one with the same title are checked too
+    let auxiliary = program.auxilary.values().chain(&program.shadowed);
+    let recipes = std::iter::once((&program.main, false)).chain(auxiliary.map(|r| (r, true)));
+    for (recipe, auxiliary) in recipes {
+        diagnostics.extend(validate_ingredient_declarations(recipe));
+        diagnostics.extend(validate_ingredient_references(recipe));
+        diagnostics.extend(validate_loop_verbs(recipe));
+        diagnostics.extend(validate_control_flow(recipe, auxiliary));
+    }
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.span.start, diagnostic.severity));
+    diagnostics
+}
+
+/// Whether any of the diagnostics is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+/// Reports recipes titled the same as an earlier one, ignoring case. Only the last of them can be
+/// served with, and one titled the same as the main recipe can never be the main recipe.
+fn validate_recipe_titles(program: &ChefProgram<'_>) -> Vec<Diagnostic> {
+    let mut first_by_title = HashMap::new();
+    let mut diagnostics = vec![];
+    for (index, Spanned(title, span)) in program.recipes.iter().enumerate() {
+        let span = title_span(title, *span);
+        let Some(&(first, first_span)) = first_by_title.get(&title.to_lowercase()) else {
+            first_by_title.insert(title.to_lowercase(), (index, span));
+            continue;
+        };
+        let diagnostic = match first {
+            0 => Diagnostic::error(
+                format!("Recipe `{title}` has the same title as the main recipe"),
+                span,
+            )
+            .with_label(first_span, "The main recipe is defined here"),
+            _ => Diagnostic::error(format!("Recipe `{title}` is defined more than once"), span)
+                .with_label(
+                    first_span,
+                    "First defined here, but replaced by the later one",
+                ),
+        };
+        diagnostics.push(diagnostic);
+    }
+    diagnostics
+}
+
+/// The span of the title at the start of a recipe.
+fn title_span(title: &str, recipe: SimpleSpan) -> SimpleSpan {
+    SimpleSpan::new(recipe.start, recipe.start + title.len())
+}
+
+/// Warns about ingredients declared more than once in a recipe, ignoring case. The last
+/// declaration decides the kind, and the last one with a value decides the initial value.
+fn validate_ingredient_declarations(
+    recipe: &ChefRecipe<'_, Instruction<'_>, Ingredient<'_>>,
+) -> Vec<Diagnostic> {
+    let mut declarations: HashMap<String, Vec<&Spanned<Ingredient>>> = HashMap::new();
+    for declaration in &recipe.ingredients {
+        declarations
+            .entry(declaration.0.name.to_lowercase())
+            .or_default()
+            .push(declaration);
     }
+    let mut diagnostics = vec![];
+    for declarations in declarations.values().filter(|d| d.len() > 1) {
+        let note = declaration_outcome(declarations);
+        for pair in declarations.windows(2) {
+            let (Spanned(_, earlier), Spanned(ingredient, span)) = (pair[0], pair[1]);
+            diagnostics.push(
+                Diagnostic::warning(
+                    format!(
+                        "Ingredient `{}` is declared more than once",
+                        ingredient.name
+                    ),
+                    *span,
+                )
+                .with_label(*earlier, "Previously declared here")
+                .with_note(note.clone()),
+            );
+        }
+    }
+    diagnostics
+}
 
-    Ok(())
+/// Explains which initial value and kind an ingredient declared more than once ends up with.
+fn declaration_outcome(declarations: &[&Spanned<Ingredient>]) -> String {
+    let Spanned(last, _) = declarations[declarations.len() - 1];
+    let name = last.name;
+    let valued = declarations
+        .iter()
+        .rev()
+        .find_map(|Spanned(ingredient, _)| Some((ingredient.initial_value?, ingredient.kind)));
+    match (last.initial_value, valued) {
+        (Some(value), _) => format!(
+            "The last declaration wins, so `{name}` starts at {value} and is {}",
+            kind_name(last.kind)
+        ),
+        (None, Some((value, kind))) if kind == last.kind => format!(
+            "`{name}` starts at {value} from an earlier declaration, as the last one has no value, and is {}",
+            kind_name(kind)
+        ),
+        (None, Some((value, kind))) => format!(
+            "`{name}` starts at {value} and is {} from an earlier declaration, as the last one has no value, but anything taken or folded into it later is {}",
+            kind_name(kind),
+            kind_name(last.kind)
+        ),
+        (None, None) => format!(
+            "`{name}` has no value until one is taken or folded into it, and is {} as the last declaration says",
+            kind_name(last.kind)
+        ),
+    }
+}
+
+fn kind_name(kind: IngredientKind) -> &'static str {
+    match kind {
+        IngredientKind::Dry => "dry",
+        IngredientKind::Wet => "wet",
+    }
+}
+
+/// Warns about loops whose `until` is not a past tense of the verb that opened them, which is
+/// often a sign of loops nested the wrong way.
+fn validate_loop_verbs(
+    recipe: &ChefRecipe<'_, Instruction<'_>, Ingredient<'_>>,
+) -> Vec<Diagnostic> {
+    visit(
+        recipe.instructions.iter(),
+        vec![],
+        &|Spanned(instruction, _), diagnostics| {
+            let Instruction::VerbLoop(VerbLoop { verb, until, .. }) = instruction else {
+                return;
+            };
+            let (Spanned(Verb(verb), opening), Spanned(Verb(until), closing)) = (verb, until);
+            if verbs::matches(verb, until) {
+                return;
+            }
+            let expected = &verbs::past_tenses(verb)[0];
+            diagnostics.push(
+                Diagnostic::warning(
+                    format!("Loop opened with `{verb}` is closed with `until {until}`"),
+                    *closing,
+                )
+                .with_label(*opening, format!("`{verb}` opens the loop here"))
+                .with_note(format!(
+                    "`{verb}` loops end with `until {expected}`, so this `until` may belong to another loop"
+                )),
+            );
+        },
+    )
+}
+
+/// Checks where `Set aside`, `Refrigerate` and `Serves` appear in a recipe.
+fn validate_control_flow(
+    recipe: &ChefRecipe<'_, Instruction<'_>, Ingredient<'_>>,
+    auxiliary: bool,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    check_placement(&recipe.instructions, false, &mut diagnostics);
+
+    // Only a `Serves` step ending the method, with no `Serves` paragraph after it, is final
+    let last = match &recipe.serves {
+        Some(_) => None,
+        None => recipe.instructions.last(),
+    };
+    let serves = visit(recipe.instructions.iter(), vec![], &|step, serves| {
+        if let Spanned(Instruction::Serves(_), span) = step {
+            serves.push(*span);
+        }
+    });
+    for span in serves {
+        if last.is_some_and(|last| last.1 == span) {
+            continue;
+        }
+        diagnostics.push(
+            Diagnostic::error("`Serves` should be the final statement of a recipe", span)
+                .with_note(
+                    "The dishes are served here, but the spec only allows `Serves` at the end",
+                ),
+        );
+    }
+
+    if auxiliary {
+        let serves = recipe.serves.iter().map(Spanned::span).chain(last.and_then(
+            |Spanned(instruction, span)| {
+                matches!(instruction, Instruction::Serves(_)).then_some(*span)
+            },
+        ));
+        for span in serves {
+            diagnostics.push(
+                Diagnostic::note(
+                    format!("`Serves` in auxiliary recipe `{}`", recipe.title),
+                    span,
+                )
+                .with_note(
+                    "An auxiliary recipe may have all the same items as a main recipe, so its sous-chef serves its own baking dishes when it finishes, before anything the main recipe serves",
+                ),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// Reports `Set aside` outside of loops, and steps that can never run because a `Refrigerate`
+/// before them always ends the recipe.
+fn check_placement(
+    instructions: &[Spanned<Instruction<'_>>],
+    in_loop: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for Spanned(instruction, span) in instructions {
+        match instruction {
+            Instruction::SetAside if !in_loop => diagnostics.push(Diagnostic::error(
+                "`Set aside` can only be used in a loop",
+                *span,
+            )),
+            Instruction::VerbLoop(VerbLoop { instructions, .. }) => {
+                check_placement(instructions, true, diagnostics)
+            }
+            _ => {}
+        }
+    }
+
+    let refrigerate = instructions
+        .iter()
+        .position(|Spanned(instruction, _)| matches!(instruction, Instruction::Refrigerate(_)));
+    let Some(refrigerate) = refrigerate else {
+        return;
+    };
+    if let Some(Spanned(_, span)) = instructions.get(refrigerate + 1) {
+        let diagnostic = Diagnostic::warning("Step is unreachable", *span).with_label(
+            instructions[refrigerate].1,
+            "`Refrigerate` always ends the recipe here",
+        );
+        let diagnostic = match instructions.len() - refrigerate - 2 {
+            0 => diagnostic,
+            1 => diagnostic.with_note("The step after this one is unreachable too"),
+            more => diagnostic.with_note(format!(
+                "The {more} steps after this one are unreachable too"
+            )),
+        };
+        diagnostics.push(diagnostic);
+    }
 }
 
 fn validate_ingredient_references(
     recipe: &ChefRecipe<'_, Instruction<'_>, Ingredient<'_>>,
-) -> Result<(), SpatulaError> {
+) -> Vec<Diagnostic> {
     let available_ingredients_by_name = recipe
         .ingredients
         .iter()
         .map(|Spanned(ingredient, _)| ingredient.name.to_lowercase())
         .collect::<HashSet<_>>();
-    let errors = visit(
+    visit(
         recipe.instructions.iter(),
         vec![],
-        &|Spanned(instruction, span), errors| match instruction {
-            Instruction::Take(i)
-            | Instruction::Put(i, _)
-            | Instruction::Fold(i, _)
-            | Instruction::Add(i, _)
-            | Instruction::Remove(i, _)
-            | Instruction::Combine(i, _)
-            | Instruction::Divide(i, _)
-            | Instruction::Liquefy(i)
-            | Instruction::StirIngredient(i, _) => {
-                if !available_ingredients_by_name.contains(i.to_lowercase().as_str()) {
-                    errors.push(SpatulaError::new(
-                        format!("Ingredient `{}` not found", i),
+        &|Spanned(instruction, span), errors| {
+            let mut check = |name: &str| {
+                if !available_ingredients_by_name.contains(name.to_lowercase().as_str()) {
+                    errors.push(Diagnostic::error(
+                        format!("Ingredient `{}` not found", name),
                         *span,
                     ));
                 }
+            };
+            match instruction {
+                Instruction::Take(i)
+                | Instruction::Put(i, _)
+                | Instruction::Fold(i, _)
+                | Instruction::Add(i, _)
+                | Instruction::Remove(i, _)
+                | Instruction::Combine(i, _)
+                | Instruction::Divide(i, _)
+                | Instruction::Liquefy(i)
+                | Instruction::StirIngredient(i, _) => check(i),
+                Instruction::VerbLoop(VerbLoop {
+                    ingredient,
+                    until_ingredient,
+                    ..
+                }) => {
+                    check(ingredient);
+                    if let Some(i) = until_ingredient {
+                        check(i);
+                    }
+                }
+                _ => {}
             }
-            _ => {}
         },
-    );
-
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(errors.into_iter().next().unwrap())
-    }
+    )
 }
 
-fn validate_recipe_references(program: &ChefProgram<'_>) -> Result<(), SpatulaError> {
+fn validate_recipe_references(program: &ChefProgram<'_>) -> Vec<Diagnostic> {
     let all_instructions = program.main.instructions.iter().chain(
         program
             .auxilary
             .values()
+            .chain(&program.shadowed)
             .flat_map(|r| r.instructions.iter()),
     );
 
@@ -72,19 +381,20 @@ fn validate_recipe_references(program: &ChefProgram<'_>) -> Result<(), SpatulaEr
     );
 
     let available_recipes = program.auxilary.keys();
-    for (reference, span) in references {
-        if !program
-            .auxilary
-            .contains_key(reference.to_lowercase().as_str())
-        {
-            return Err(SpatulaError::new(
+    references
+        .into_iter()
+        .filter(|(reference, _)| {
+            !program
+                .auxilary
+                .contains_key(reference.to_lowercase().as_str())
+        })
+        .map(|(reference, span)| {
+            Diagnostic::error(
                 format!("Recipe `{reference}` not found. Available recipes: {available_recipes:?}"),
                 span,
-            ));
-        }
-    }
-
-    Ok(())
+            )
+        })
+        .collect()
 }
 
 fn visit<'a, I, A, F>(instructions: I, mut arg: A, visitor: &F) -> A
@@ -100,3 +410,244 @@ where
     }
     arg
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Validates a recipe written inline.
+    fn diagnostics(source: &str) -> Vec<Diagnostic> {
+        validate(&crate::parser::parse_recipe(source))
+    }
+
+    /// The severity of each diagnostic, with the source it points at and at its first label.
+    fn spans<'s>(
+        source: &'s str,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<(Severity, &'s str, Option<&'s str>)> {
+        let text = |span: SimpleSpan| source[span.into_range()].trim();
+        diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let label = diagnostic.labels.first().map(|(span, _)| text(*span));
+                (diagnostic.severity, text(diagnostic.span), label)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_diagnostic() {
+        let source = r#"
+Typo Soup.
+
+Ingredients.
+1 egg
+
+Method.
+Put eg into mixing bowl. Serve with crouton. Beat the eggs. Fold sugar into mixing bowl. Beat until beaten. Serve with crutons.
+"#
+        .trim();
+        let diagnostics = diagnostics(source);
+        assert_eq!(
+            spans(source, &diagnostics),
+            [
+                (Severity::Error, "Put eg into mixing bowl", None),
+                (Severity::Error, "Serve with crouton", None),
+                (
+                    Severity::Error,
+                    "Beat the eggs. Fold sugar into mixing bowl. Beat until beaten",
+                    None
+                ),
+                (Severity::Error, "Fold sugar into mixing bowl", None),
+                (Severity::Error, "Serve with crutons", None),
+            ]
+        );
+        assert!(has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn test_duplicate_titles() {
+        let source = r#"
+Soup.
+
+Ingredients.
+1 egg
+
+Method.
+Serve with bread.
+
+Bread.
+
+Ingredients.
+1 egg
+
+Method.
+Put eg into mixing bowl. Serve with gravy.
+
+soup.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl.
+
+BREAD.
+
+Ingredients.
+2 egg
+
+Method.
+Put egg into mixing bowl.
+"#
+        .trim();
+        let diagnostics = diagnostics(source);
+        // The shadowed `Bread` is still checked
+        assert_eq!(
+            spans(source, &diagnostics),
+            [
+                (Severity::Error, "Put eg into mixing bowl", None),
+                (Severity::Error, "Serve with gravy", None),
+                (Severity::Error, "soup", Some("Soup")),
+                (Severity::Error, "BREAD", Some("Bread")),
+            ]
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "Recipe `soup` has the same title as the main recipe"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_ingredients() {
+        let source = r#"
+Shadowed Stew.
+
+Ingredients.
+1 egg
+2 ml Egg
+3 pepper
+1 l salt
+pepper
+salt
+SALT
+
+Method.
+Put egg into mixing bowl.
+"#
+        .trim();
+        let diagnostics = diagnostics(source);
+        assert_eq!(
+            spans(source, &diagnostics),
+            [
+                (Severity::Warning, "2 ml Egg", Some("1 egg")),
+                (Severity::Warning, "pepper", Some("3 pepper")),
+                (Severity::Warning, "salt", Some("1 l salt")),
+                (Severity::Warning, "SALT", Some("salt")),
+            ]
+        );
+        let notes: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.note.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                "The last declaration wins, so `Egg` starts at 2 and is wet",
+                "`pepper` starts at 3 from an earlier declaration, as the last one has no value, and is dry",
+                "`SALT` starts at 1 and is wet from an earlier declaration, as the last one has no value, but anything taken or folded into it later is dry",
+                "`SALT` starts at 1 and is wet from an earlier declaration, as the last one has no value, but anything taken or folded into it later is dry",
+            ]
+        );
+        assert!(!has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn test_loop_verbs() {
+        let source = r#"
+Tangled Loops.
+
+Ingredients.
+1 egg
+2 g flour
+
+Method.
+Sift the flour. Beat the egg. Put egg into mixing bowl. Shake the flour until sifted. Whisk the egg until beaten.
+Fry the egg. Stir the flour. Set aside. Stir until stirred. Fry until fried.
+"#
+        .trim();
+        let diagnostics = diagnostics(source);
+        assert_eq!(
+            spans(source, &diagnostics),
+            [
+                (
+                    Severity::Warning,
+                    "Shake the flour until sifted",
+                    Some("Beat the egg")
+                ),
+                (
+                    Severity::Warning,
+                    "Whisk the egg until beaten",
+                    Some("Sift the flour")
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Loop opened with `Beat` is closed with `until sifted`"
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = r#"
+Misplaced Soup.
+
+Ingredients.
+1 egg
+
+Method.
+Set aside. Serves 1. Beat the egg. Refrigerate. Set aside. Beat until beaten. Serve with broth. Refrigerate. Clean mixing bowl. Put egg into mixing bowl.
+
+Serves 1.
+
+Broth.
+
+Ingredients.
+1 egg
+
+Method.
+Put egg into mixing bowl. Beat the egg. Set aside. Beat until beaten. Serves 2.
+"#
+        .trim();
+        let diagnostics = diagnostics(source);
+        assert_eq!(
+            spans(source, &diagnostics),
+            [
+                (Severity::Error, "Set aside", None),
+                (Severity::Error, "Serves 1", None),
+                (Severity::Warning, "Set aside", Some("Refrigerate")),
+                (Severity::Warning, "Clean mixing bowl", Some("Refrigerate")),
+                (Severity::Note, "Serves 2", None),
+            ]
+        );
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "`Set aside` can only be used in a loop",
+                "`Serves` should be the final statement of a recipe",
+                "Step is unreachable",
+                "Step is unreachable",
+                "`Serves` in auxiliary recipe `Broth`",
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].note.as_deref(),
+            Some("The step after this one is unreachable too")
+        );
+    }
+}
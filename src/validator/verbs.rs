@@ -0,0 +1,153 @@
+//! English inflection of the verbs opening and closing loops, as in `Sift the flour` and
+//! `Sift until sifted`.
+
+/// Verbs whose past tense or past participle does not end in `-ed`, with every form accepted
+/// after `until`.
+const IRREGULAR: &[(&str, &[&str])] = &[
+    ("beat", &["beaten", "beat"]),
+    ("bite", &["bitten", "bit"]),
+    ("bind", &["bound"]),
+    ("blow", &["blown", "blew"]),
+    ("break", &["broken", "broke"]),
+    ("bring", &["brought"]),
+    ("burn", &["burnt", "burned"]),
+    ("buy", &["bought"]),
+    ("catch", &["caught"]),
+    ("choose", &["chosen", "chose"]),
+    ("cut", &["cut"]),
+    ("dig", &["dug"]),
+    ("draw", &["drawn", "drew"]),
+    ("drink", &["drunk", "drank"]),
+    ("eat", &["eaten", "ate"]),
+    ("feed", &["fed"]),
+    ("find", &["found"]),
+    ("fling", &["flung"]),
+    ("forget", &["forgotten", "forgot"]),
+    ("freeze", &["frozen", "froze"]),
+    ("get", &["got", "gotten"]),
+    ("give", &["given", "gave"]),
+    ("go", &["gone", "went"]),
+    ("grind", &["ground"]),
+    ("hang", &["hung", "hanged"]),
+    ("hide", &["hidden", "hid"]),
+    ("hit", &["hit"]),
+    ("hold", &["held"]),
+    ("keep", &["kept"]),
+    ("knit", &["knit", "knitted"]),
+    ("lay", &["laid"]),
+    ("leave", &["left"]),
+    ("let", &["let"]),
+    ("lie", &["lain", "lay"]),
+    ("light", &["lit", "lighted"]),
+    ("make", &["made"]),
+    ("put", &["put"]),
+    ("rise", &["risen", "rose"]),
+    ("run", &["run", "ran"]),
+    ("see", &["seen", "saw"]),
+    ("seek", &["sought"]),
+    ("set", &["set"]),
+    ("shake", &["shaken", "shook"]),
+    ("shed", &["shed"]),
+    ("shrink", &["shrunk", "shrank"]),
+    ("shut", &["shut"]),
+    ("sink", &["sunk", "sank"]),
+    ("sit", &["sat"]),
+    ("slay", &["slain", "slew"]),
+    ("slide", &["slid"]),
+    ("sling", &["slung"]),
+    ("slit", &["slit"]),
+    ("smell", &["smelt", "smelled"]),
+    ("spill", &["spilt", "spilled"]),
+    ("spin", &["spun"]),
+    ("spit", &["spat", "spit"]),
+    ("split", &["split"]),
+    ("spoil", &["spoilt", "spoiled"]),
+    ("spread", &["spread"]),
+    ("spring", &["sprung", "sprang"]),
+    ("stand", &["stood"]),
+    ("steal", &["stolen", "stole"]),
+    ("stick", &["stuck"]),
+    ("sting", &["stung"]),
+    ("strike", &["struck"]),
+    ("string", &["strung"]),
+    ("sweep", &["swept"]),
+    ("swell", &["swollen", "swelled"]),
+    ("swing", &["swung"]),
+    ("take", &["taken", "took"]),
+    ("tear", &["torn", "tore"]),
+    ("throw", &["thrown", "threw"]),
+    ("thrust", &["thrust"]),
+    ("wake", &["woken", "woke"]),
+    ("weave", &["woven", "wove"]),
+    ("wind", &["wound"]),
+    ("wring", &["wrung"]),
+];
+
+/// The forms of `verb` that may close a loop it opens, the most likely first. Irregular verbs
+/// take the forms in their table, and others the `-ed` forms that regular English spelling
+/// allows.
+pub fn past_tenses(verb: &str) -> Vec<String> {
+    let verb = verb.to_lowercase();
+    if let Some((_, forms)) = IRREGULAR.iter().find(|(base, _)| *base == verb) {
+        return forms.iter().map(|form| form.to_string()).collect();
+    }
+
+    let chars: Vec<char> = verb.chars().collect();
+    let is_vowel = |c: char| "aeiou".contains(c);
+    match chars.as_slice() {
+        // bake -> baked, while sauté falls through to sautéed
+        [.., 'e'] => vec![format!("{verb}d")],
+        // fry -> fried, but stay -> stayed
+        [.., c, 'y'] if !is_vowel(*c) => vec![format!("{}ied", &verb[..verb.len() - 1])],
+        // panic -> panicked
+        [.., 'c'] => vec![format!("{verb}ked"), format!("{verb}ed")],
+        // chop -> chopped, but also open -> opened, as it depends on stress
+        [.., a, b, c] if !is_vowel(*a) && is_vowel(*b) && !is_vowel(*c) && !"wxy".contains(*c) => {
+            vec![format!("{verb}{c}ed"), format!("{verb}ed")]
+        }
+        _ => vec![format!("{verb}ed")],
+    }
+}
+
+/// Whether `until` is a past tense of `verb`, ignoring case.
+pub fn matches(verb: &str, until: &str) -> bool {
+    let until = until.to_lowercase();
+    past_tenses(verb).contains(&until)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inflection() {
+        for (verb, until) in [
+            ("Sift", "sifted"),
+            ("Bake", "baked"),
+            ("Fry", "fried"),
+            ("Stay", "stayed"),
+            ("Chop", "chopped"),
+            ("Stir", "stirred"),
+            ("Open", "opened"),
+            ("Mix", "mixed"),
+            ("Panic", "panicked"),
+            ("Beat", "beaten"),
+            ("Shake", "shook"),
+            ("Freeze", "FROZEN"),
+            ("Cut", "cut"),
+            ("Sauté", "sautéed"),
+        ] {
+            assert!(matches(verb, until), "{verb} until {until}");
+        }
+        for (verb, until) in [
+            ("Sift", "shaken"),
+            ("Shake", "shaked"),
+            ("Fry", "fryed"),
+            ("Beat", "beated"),
+            ("Mix", "mixxed"),
+            ("Sauté", "sautéd"),
+        ] {
+            assert!(!matches(verb, until), "{verb} until {until}");
+        }
+    }
+}